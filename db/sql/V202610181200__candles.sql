@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS candles (
+  source VARCHAR(255) NOT NULL,
+  symbol VARCHAR(255) NOT NULL,
+  interval VARCHAR(8) NOT NULL,
+  open_time INTEGER NOT NULL,
+  open REAL,
+  high REAL,
+  low REAL,
+  close REAL,
+  volume REAL NOT NULL DEFAULT 0,
+  tick_count INTEGER NOT NULL DEFAULT 0,
+
+  PRIMARY KEY (source, symbol, interval, open_time)
+);
@@ -0,0 +1,34 @@
+-- Currencies hold canonical asset codes in `symbol`, the seed data had them swapped
+UPDATE currencies SET name = 'Bitcoin', symbol = 'BTC' WHERE id = 1;
+UPDATE currencies SET name = 'Ethereum', symbol = 'ETH' WHERE id = 2;
+
+INSERT INTO currencies (id, name, symbol) VALUES (3, 'US Dollar', 'USD');
+INSERT INTO currencies (id, name, symbol) VALUES (4, 'Euro', 'EUR');
+INSERT INTO currencies (id, name, symbol) VALUES (5, 'Tether', 'USDT');
+INSERT INTO currencies (id, name, symbol) VALUES (6, 'USD Coin', 'USDC');
+INSERT INTO currencies (id, name, symbol) VALUES (7, 'Dogecoin', 'DOGE');
+INSERT INTO currencies (id, name, symbol) VALUES (8, 'Solana', 'SOL');
+
+INSERT INTO providers (id, name) VALUES (2, 'Binance');
+INSERT INTO providers (id, name) VALUES (3, 'Kraken');
+INSERT INTO providers (id, name) VALUES (4, 'OKX');
+INSERT INTO providers (id, name) VALUES (5, 'Bybit');
+
+-- Venue specific codes of the canonical currencies
+CREATE TABLE IF NOT EXISTS currency_aliases (
+  provider_id INT NOT NULL,
+  alias VARCHAR(255) NOT NULL,
+  currency_id INT NOT NULL,
+
+  PRIMARY KEY (provider_id, alias),
+  FOREIGN KEY (provider_id) REFERENCES providers (id),
+  FOREIGN KEY (currency_id) REFERENCES currencies (id)
+);
+
+INSERT INTO currency_aliases (provider_id, alias, currency_id) VALUES (3, 'XBT', 1);
+INSERT INTO currency_aliases (provider_id, alias, currency_id) VALUES (3, 'XXBT', 1);
+INSERT INTO currency_aliases (provider_id, alias, currency_id) VALUES (3, 'XETH', 2);
+INSERT INTO currency_aliases (provider_id, alias, currency_id) VALUES (3, 'ZUSD', 3);
+INSERT INTO currency_aliases (provider_id, alias, currency_id) VALUES (3, 'ZEUR', 4);
+INSERT INTO currency_aliases (provider_id, alias, currency_id) VALUES (3, 'XDG', 7);
+INSERT INTO currency_aliases (provider_id, alias, currency_id) VALUES (3, 'XXDG', 7);
@@ -0,0 +1,18 @@
+-- The foreign keys of tickers and ticker_prices name tables that do not exist
+-- (provider, currency, Ticker), so no row could be inserted with foreign keys
+-- enforced. Renaming a table rewrites the foreign keys pointing at it, which
+-- points them at the real tables.
+ALTER TABLE providers RENAME TO provider;
+ALTER TABLE provider RENAME TO providers;
+ALTER TABLE currencies RENAME TO currency;
+ALTER TABLE currency RENAME TO currencies;
+ALTER TABLE tickers RENAME TO Ticker;
+ALTER TABLE Ticker RENAME TO tickers;
+
+-- Prices are stored as an unscaled integer `amount` with the DECIMAL
+-- `precision` and `scale` of the price the venue sent, i.e. 0.0100 is stored
+-- as (100, 4, 4) and 12.50 as (1250, 4, 2). `created_at` holds the publish
+-- time with milliseconds.
+ALTER TABLE ticker_prices ADD COLUMN amount INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS ticker_price_ticker_id_created_at_idx ON ticker_prices(ticker_id, created_at);
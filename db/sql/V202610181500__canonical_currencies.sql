@@ -0,0 +1,8 @@
+-- The price writer adds the currencies of instruments missing from the seed
+-- data so that their prices can be stored. Those are venue codes, not curated
+-- assets, and are kept out of the asset registry.
+ALTER TABLE currencies ADD COLUMN canonical BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- Currencies added by the price writer before the flag existed, the seed data
+-- names every canonical currency
+UPDATE currencies SET canonical = FALSE WHERE name IS NULL OR name = symbol;
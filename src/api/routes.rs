@@ -1,26 +1,74 @@
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
 use axum::{
-    extract::Extension,
+    extract::{Extension, Query, State},
     http::StatusCode,
     response::{Html, IntoResponse},
     Json,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 use crate::graphql::ServiceSchema;
+use crate::services::dead_letters::DeadLetter;
+use crate::services::supervisor::{FeedState, FeedStatus};
+use crate::AppContext;
 
 #[derive(Serialize)]
 struct Health {
+    /// False when no ticker feed is live or polled over the REST fallback,
+    /// order book and trade feeds don't count. Replays are always healthy.
+    /// One venue going down only shows up in `down`.
     healthy: bool,
+    /// Feeds which are not live: connecting, stale, failed or dead
+    down: Vec<String>,
+    feeds: Vec<FeedStatus>,
+}
+
+#[derive(Serialize)]
+struct DeadLetters {
+    /// Decode failures per feed since startup
+    counts: BTreeMap<String, u64>,
+    letters: Vec<DeadLetter>,
+}
+
+#[derive(Deserialize)]
+pub struct DeadLettersQuery {
+    feed: Option<String>,
 }
 
 pub async fn root() -> impl IntoResponse {
     (StatusCode::OK, Json("Hello world"))
 }
 
-pub async fn health() -> impl IntoResponse {
-    (StatusCode::OK, Json(Health { healthy: true }))
+pub async fn health(State(state): State<AppContext>) -> impl IntoResponse {
+    let feeds = state.supervisor.feeds();
+    // Replays feed the pipeline from a journal without any feeds
+    let replaying = !state.config.replay_file.is_empty();
+    let healthy = replaying
+        || feeds
+            .iter()
+            .any(|feed| feed.serves_tickers && (feed.state == FeedState::Live || feed.fallback));
+    let down = feeds
+        .iter()
+        .filter(|feed| feed.state != FeedState::Live)
+        .map(|feed| feed.feed.clone())
+        .collect();
+
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(Health {
+            healthy,
+            down,
+            feeds,
+        }),
+    )
 }
 
 pub async fn graphql_playground() -> impl IntoResponse {
@@ -33,3 +81,21 @@ pub async fn graphql_handler(
 ) -> GraphQLResponse {
     schema.execute(req.into_inner()).await.into()
 }
+
+pub async fn get_dead_letters(
+    State(state): State<AppContext>,
+    Query(query): Query<DeadLettersQuery>,
+) -> impl IntoResponse {
+    let dead_letters = DeadLetters {
+        counts: state.dead_letters.counts(),
+        letters: state.dead_letters.letters(query.feed.as_deref()),
+    };
+
+    (StatusCode::OK, Json(dead_letters))
+}
+
+pub async fn clear_dead_letters(State(state): State<AppContext>) -> impl IntoResponse {
+    state.dead_letters.clear();
+
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,176 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use tokio::time::{interval, sleep_until, Duration, Instant};
+
+use crate::{markets, Faults, MockConfig, Prices};
+
+#[derive(Deserialize, Debug)]
+struct StreamRequest {
+    method: String,
+    #[serde(default)]
+    params: Vec<String>,
+    id: Value,
+}
+
+pub fn routes() -> Router<MockConfig> {
+    Router::new()
+        .route("/api/v3/exchangeInfo", get(exchange_info))
+        .route("/api/v3/ticker/price", get(ticker_price))
+        .route("/stream", get(stream_handler))
+}
+
+fn symbol(base: &str, quote: &str) -> String {
+    format!("{}{}", base, quote)
+}
+
+async fn exchange_info() -> Json<Value> {
+    let symbols = markets()
+        .map(|(base, quote)| {
+            json!({
+                "symbol": symbol(base, quote),
+                "status": "TRADING",
+                "baseAsset": base,
+                "quoteAsset": quote,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Json(json!({ "symbols": symbols }))
+}
+
+async fn ticker_price() -> Json<Value> {
+    let mut prices = Prices::new(symbol);
+    let tickers = markets()
+        .map(|(base, quote)| {
+            let symbol = symbol(base, quote);
+            let price = format!("{:.2}", prices.tick(&symbol));
+
+            json!({ "symbol": symbol, "price": price })
+        })
+        .collect::<Vec<_>>();
+
+    Json(Value::Array(tickers))
+}
+
+async fn stream_handler(ws: WebSocketUpgrade, State(config): State<MockConfig>) -> Response {
+    ws.on_upgrade(|socket| stream(socket, config))
+}
+
+/// Combined stream endpoint, sends `@ticker` and `@trade` streams of the
+/// subscribed markets, other streams are acknowledged but stay silent
+async fn stream(socket: WebSocket, config: MockConfig) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut prices = Prices::new(symbol);
+    let mut faults = Faults::new(&config);
+    let mut streams = BTreeSet::new();
+    let mut trade_id = 0;
+
+    let mut tick_timer = interval(Duration::from_millis(config.tick_ms));
+    let disconnect_at = Instant::now() + Duration::from_secs(config.disconnect_secs);
+
+    info!("Binance client connected");
+
+    loop {
+        let frames = tokio::select! {
+            message = receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                let request: StreamRequest = match serde_json::from_str(&text) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        warn!("Invalid Binance request: {}", err);
+                        continue;
+                    }
+                };
+
+                match request.method.as_str() {
+                    "SUBSCRIBE" => streams.extend(request.params),
+                    "UNSUBSCRIBE" => {
+                        for stream in &request.params {
+                            streams.remove(stream);
+                        }
+                    }
+                    method => warn!("Unknown Binance method: {}", method),
+                }
+
+                vec![Message::Text(json!({ "result": null, "id": request.id }).to_string())]
+            }
+            _ = tick_timer.tick() => {
+                let now = Utc::now().timestamp_millis();
+
+                streams
+                    .iter()
+                    .filter_map(|stream| {
+                        let (symbol, kind) = stream.split_once('@')?;
+                        let symbol = symbol.to_uppercase();
+                        if !prices.contains(&symbol) {
+                            return None;
+                        }
+
+                        let price = prices.tick(&symbol);
+                        let data = match kind {
+                            "ticker" => json!({
+                                "e": "24hrTicker",
+                                "E": now,
+                                "s": symbol,
+                                "c": format!("{:.2}", price),
+                                "o": format!("{:.2}", price * 0.99),
+                                "h": format!("{:.2}", price * 1.02),
+                                "l": format!("{:.2}", price * 0.98),
+                                "v": "1000.00",
+                                "q": format!("{:.2}", price * 1000.0),
+                                "b": format!("{:.2}", price * 0.9999),
+                                "a": format!("{:.2}", price * 1.0001),
+                            }),
+                            "trade" => {
+                                trade_id += 1;
+                                json!({
+                                    "e": "trade",
+                                    "E": now,
+                                    "s": symbol,
+                                    "t": trade_id,
+                                    "p": format!("{:.2}", price),
+                                    "q": "0.01",
+                                    "T": now,
+                                    "m": trade_id % 2 == 0,
+                                })
+                            }
+                            _ => return None,
+                        };
+
+                        Some(faults.frame(json!({ "stream": stream, "data": data }).to_string()))
+                    })
+                    .collect()
+            }
+            _ = sleep_until(disconnect_at), if config.disconnect_secs > 0 => {
+                info!("Dropping Binance client");
+                return;
+            }
+        };
+
+        for frame in frames {
+            if sender.send(frame).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    info!("Binance client disconnected");
+}
@@ -0,0 +1,157 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use tokio::time::{interval, sleep_until, Duration, Instant};
+
+use crate::{markets, Faults, MockConfig, Prices};
+
+#[derive(Deserialize, Debug)]
+struct OpRequest {
+    op: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+pub fn routes() -> Router<MockConfig> {
+    Router::new()
+        .route("/v5/market/instruments-info", get(instruments_info))
+        .route("/v5/public/spot", get(ws_handler))
+}
+
+fn symbol(base: &str, quote: &str) -> String {
+    format!("{}{}", base, quote)
+}
+
+async fn instruments_info() -> Json<Value> {
+    let instruments = markets()
+        .map(|(base, quote)| {
+            json!({
+                "symbol": symbol(base, quote),
+                "baseCoin": base,
+                "quoteCoin": quote,
+                "status": "Trading",
+                "priceFilter": { "tickSize": "0.01" },
+                "lotSizeFilter": { "basePrecision": "0.0001", "minOrderAmt": "1" },
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Json(json!({
+        "retCode": 0,
+        "retMsg": "OK",
+        "result": { "category": "spot", "list": instruments },
+    }))
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(config): State<MockConfig>) -> Response {
+    ws.on_upgrade(|socket| feed(socket, config))
+}
+
+/// Spot public WebSocket, sends the `tickers.*` topics of the subscribed
+/// symbols and answers `ping` ops
+async fn feed(socket: WebSocket, config: MockConfig) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut prices = Prices::new(symbol);
+    let mut faults = Faults::new(&config);
+    let mut topics = BTreeSet::new();
+
+    let mut tick_timer = interval(Duration::from_millis(config.tick_ms));
+    let disconnect_at = Instant::now() + Duration::from_secs(config.disconnect_secs);
+
+    info!("Bybit client connected");
+
+    loop {
+        let frames = tokio::select! {
+            message = receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                let request: OpRequest = match serde_json::from_str(&text) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        warn!("Invalid Bybit request: {}", err);
+                        continue;
+                    }
+                };
+
+                let ret_msg = match request.op.as_str() {
+                    "ping" => "pong",
+                    "subscribe" => {
+                        topics.extend(request.args);
+                        ""
+                    }
+                    "unsubscribe" => {
+                        for topic in &request.args {
+                            topics.remove(topic);
+                        }
+                        ""
+                    }
+                    op => {
+                        warn!("Unknown Bybit op: {}", op);
+                        continue;
+                    }
+                };
+
+                vec![Message::Text(
+                    json!({ "success": true, "ret_msg": ret_msg, "op": request.op }).to_string(),
+                )]
+            }
+            _ = tick_timer.tick() => {
+                let ts = Utc::now().timestamp_millis();
+
+                topics
+                    .iter()
+                    .filter_map(|topic| {
+                        let symbol = topic.strip_prefix("tickers.")?;
+                        if !prices.contains(symbol) {
+                            return None;
+                        }
+
+                        let price = prices.tick(symbol);
+                        let data = json!({
+                            "symbol": symbol,
+                            "lastPrice": format!("{:.2}", price),
+                            "prevPrice24h": format!("{:.2}", price * 0.99),
+                            "highPrice24h": format!("{:.2}", price * 1.02),
+                            "lowPrice24h": format!("{:.2}", price * 0.98),
+                            "volume24h": "1000.00",
+                            "turnover24h": format!("{:.2}", price * 1000.0),
+                        });
+
+                        Some(faults.frame(
+                            json!({ "topic": topic, "ts": ts, "type": "snapshot", "data": data })
+                                .to_string(),
+                        ))
+                    })
+                    .collect()
+            }
+            _ = sleep_until(disconnect_at), if config.disconnect_secs > 0 => {
+                info!("Dropping Bybit client");
+                return;
+            }
+        };
+
+        for frame in frames {
+            if sender.send(frame).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    info!("Bybit client disconnected");
+}
@@ -0,0 +1,197 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use tokio::time::{interval, sleep_until, Duration, Instant};
+
+use crate::{markets, Faults, MockConfig, Prices};
+
+#[derive(Deserialize, Debug)]
+struct ChannelRequest {
+    #[serde(rename = "type")]
+    message_type: String,
+    #[serde(default)]
+    channels: Vec<Channel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Channel {
+    name: String,
+    #[serde(default)]
+    product_ids: Vec<String>,
+}
+
+pub fn routes() -> Router<MockConfig> {
+    Router::new()
+        .route("/", get(feed_handler))
+        .route("/products", get(products))
+        .route("/products/:product_id/ticker", get(product_ticker))
+}
+
+fn symbol(base: &str, quote: &str) -> String {
+    format!("{}-{}", base, quote)
+}
+
+async fn products() -> Json<Value> {
+    let products = markets()
+        .map(|(base, quote)| {
+            json!({
+                "id": symbol(base, quote),
+                "base_currency": base,
+                "quote_currency": quote,
+                "status": "online",
+                "trading_disabled": false,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Json(Value::Array(products))
+}
+
+async fn product_ticker(Path(product_id): Path<String>) -> Result<Json<Value>, StatusCode> {
+    let mut prices = Prices::new(symbol);
+    if !prices.contains(&product_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let price = prices.tick(&product_id);
+
+    Ok(Json(json!({
+        "price": format!("{:.2}", price),
+        "bid": format!("{:.2}", price * 0.9999),
+        "ask": format!("{:.2}", price * 1.0001),
+        "volume": "1000.00",
+        "time": Utc::now().to_rfc3339(),
+    })))
+}
+
+async fn feed_handler(ws: WebSocketUpgrade, State(config): State<MockConfig>) -> Response {
+    ws.on_upgrade(|socket| feed(socket, config))
+}
+
+/// Sends the `ticker` and `matches` channels of the subscribed products,
+/// other channels are acknowledged but stay silent
+async fn feed(socket: WebSocket, config: MockConfig) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut prices = Prices::new(symbol);
+    let mut faults = Faults::new(&config);
+    // Channel name -> product ids
+    let mut channels: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut sequence = 0;
+
+    let mut tick_timer = interval(Duration::from_millis(config.tick_ms));
+    let disconnect_at = Instant::now() + Duration::from_secs(config.disconnect_secs);
+
+    info!("Coinbase client connected");
+
+    loop {
+        let frames = tokio::select! {
+            message = receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                let request: ChannelRequest = match serde_json::from_str(&text) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        warn!("Invalid Coinbase request: {}", err);
+                        continue;
+                    }
+                };
+
+                for channel in request.channels {
+                    let products = channels.entry(channel.name).or_default();
+                    match request.message_type.as_str() {
+                        "subscribe" => products.extend(channel.product_ids),
+                        "unsubscribe" => {
+                            for product_id in &channel.product_ids {
+                                products.remove(product_id);
+                            }
+                        }
+                        message_type => warn!("Unknown Coinbase request: {}", message_type),
+                    }
+                }
+
+                let subscriptions = channels
+                    .iter()
+                    .map(|(name, product_ids)| json!({ "name": name, "product_ids": product_ids }))
+                    .collect::<Vec<_>>();
+
+                vec![Message::Text(
+                    json!({ "type": "subscriptions", "channels": subscriptions }).to_string(),
+                )]
+            }
+            _ = tick_timer.tick() => {
+                let time = Utc::now().to_rfc3339();
+                let mut frames = vec![];
+
+                for (channel, product_ids) in &channels {
+                    for product_id in product_ids {
+                        if !prices.contains(product_id) {
+                            continue;
+                        }
+
+                        sequence += 1;
+                        let price = prices.tick(product_id);
+                        let frame = match channel.as_str() {
+                            "ticker" => json!({
+                                "type": "ticker",
+                                "sequence": sequence,
+                                "product_id": product_id,
+                                "price": format!("{:.2}", price),
+                                "open_24h": format!("{:.2}", price * 0.99),
+                                "high_24h": format!("{:.2}", price * 1.02),
+                                "low_24h": format!("{:.2}", price * 0.98),
+                                "volume_24h": "1000.00",
+                                "best_bid": format!("{:.2}", price * 0.9999),
+                                "best_ask": format!("{:.2}", price * 1.0001),
+                                "time": time,
+                            }),
+                            "matches" => json!({
+                                "type": "match",
+                                "trade_id": sequence,
+                                "sequence": sequence,
+                                "product_id": product_id,
+                                "price": format!("{:.2}", price),
+                                "size": "0.01",
+                                "side": if sequence % 2 == 0 { "buy" } else { "sell" },
+                                "time": time,
+                            }),
+                            _ => continue,
+                        };
+
+                        frames.push(faults.frame(frame.to_string()));
+                    }
+                }
+
+                frames
+            }
+            _ = sleep_until(disconnect_at), if config.disconnect_secs > 0 => {
+                info!("Dropping Coinbase client");
+                return;
+            }
+        };
+
+        for frame in frames {
+            if sender.send(frame).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    info!("Coinbase client disconnected");
+}
@@ -0,0 +1,178 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use tokio::time::{interval, sleep_until, Duration, Instant};
+
+use crate::{markets, Faults, MockConfig, Prices};
+
+#[derive(Deserialize, Debug)]
+struct MethodRequest {
+    method: String,
+    #[serde(default)]
+    params: Option<Params>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    channel: String,
+    #[serde(default)]
+    symbol: Vec<String>,
+}
+
+pub fn routes() -> Router<MockConfig> {
+    Router::new()
+        .route("/0/public/AssetPairs", get(asset_pairs))
+        .route("/v2", get(ws_handler))
+}
+
+fn symbol(base: &str, quote: &str) -> String {
+    format!("{}/{}", base, quote)
+}
+
+/// The REST API lists bitcoin under its legacy code
+fn legacy_code(code: &str) -> &str {
+    match code {
+        "BTC" => "XBT",
+        code => code,
+    }
+}
+
+async fn asset_pairs() -> Json<Value> {
+    let pairs = markets()
+        .map(|(base, quote)| {
+            let pair = json!({
+                "wsname": symbol(legacy_code(base), quote),
+                "status": "online",
+                "tick_size": "0.01",
+                "costmin": "0.5",
+            });
+
+            (format!("{}{}", legacy_code(base), quote), pair)
+        })
+        .collect::<Map<_, _>>();
+
+    Json(json!({ "error": [], "result": pairs }))
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(config): State<MockConfig>) -> Response {
+    ws.on_upgrade(|socket| feed(socket, config))
+}
+
+/// WebSocket API v2, sends the `ticker` channel of the subscribed symbols
+async fn feed(socket: WebSocket, config: MockConfig) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut prices = Prices::new(symbol);
+    let mut faults = Faults::new(&config);
+    let mut symbols = BTreeSet::new();
+
+    let mut tick_timer = interval(Duration::from_millis(config.tick_ms));
+    let disconnect_at = Instant::now() + Duration::from_secs(config.disconnect_secs);
+
+    info!("Kraken client connected");
+
+    loop {
+        let frames: Vec<Message> = tokio::select! {
+            message = receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                let request: MethodRequest = match serde_json::from_str(&text) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        warn!("Invalid Kraken request: {}", err);
+                        continue;
+                    }
+                };
+                let params = match request.params {
+                    Some(params) if params.channel == "ticker" => params,
+                    _ => {
+                        warn!("Unsupported Kraken request: {}", text);
+                        continue;
+                    }
+                };
+
+                match request.method.as_str() {
+                    "subscribe" => symbols.extend(params.symbol.iter().cloned()),
+                    "unsubscribe" => {
+                        for symbol in &params.symbol {
+                            symbols.remove(symbol);
+                        }
+                    }
+                    method => warn!("Unknown Kraken method: {}", method),
+                }
+
+                params
+                    .symbol
+                    .iter()
+                    .map(|symbol| {
+                        Message::Text(
+                            json!({
+                                "method": request.method,
+                                "success": true,
+                                "result": { "channel": "ticker", "symbol": symbol },
+                            })
+                            .to_string(),
+                        )
+                    })
+                    .collect()
+            }
+            _ = tick_timer.tick() => {
+                symbols
+                    .iter()
+                    .filter_map(|symbol| {
+                        if !prices.contains(symbol) {
+                            return None;
+                        }
+
+                        let price = prices.tick(symbol);
+                        let data = json!({
+                            "symbol": symbol,
+                            "last": round(price),
+                            "high": round(price * 1.02),
+                            "low": round(price * 0.98),
+                            "volume": 1000.0,
+                            "bid": round(price * 0.9999),
+                            "ask": round(price * 1.0001),
+                        });
+
+                        Some(faults.frame(
+                            json!({ "channel": "ticker", "type": "update", "data": [data] })
+                                .to_string(),
+                        ))
+                    })
+                    .collect()
+            }
+            _ = sleep_until(disconnect_at), if config.disconnect_secs > 0 => {
+                info!("Dropping Kraken client");
+                return;
+            }
+        };
+
+        for frame in frames {
+            if sender.send(frame).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    info!("Kraken client disconnected");
+}
+
+/// Kraken sends prices as JSON numbers
+fn round(price: f64) -> f64 {
+    (price * 100.0).round() / 100.0
+}
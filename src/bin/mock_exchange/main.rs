@@ -0,0 +1,151 @@
+//! Mock exchange speaking the Binance, Coinbase, Kraken, OKX and Bybit wire
+//! formats, so that the server can run without touching the live venues.
+//! Point the server at it:
+//!
+//! ```text
+//! EXCHANGES=binance,coinbase,kraken,okx,bybit
+//! BINANCE_WS_URL=ws://127.0.0.1:9090/binance/stream
+//! BINANCE_API_URL=http://127.0.0.1:9090/binance
+//! COINBASE_WS_URL=ws://127.0.0.1:9090/coinbase
+//! COINBASE_API_URL=http://127.0.0.1:9090/coinbase
+//! KRAKEN_WS_URL=ws://127.0.0.1:9090/kraken/v2
+//! KRAKEN_API_URL=http://127.0.0.1:9090/kraken
+//! OKX_WS_URL=ws://127.0.0.1:9090/okx/ws/v5/public
+//! OKX_API_URL=http://127.0.0.1:9090/okx
+//! BYBIT_WS_URL=ws://127.0.0.1:9090/bybit/v5/public/spot
+//! BYBIT_API_URL=http://127.0.0.1:9090/bybit
+//! ```
+use axum::{extract::ws::Message, Router, Server};
+use config::Environment;
+use dotenv::dotenv;
+use log::info;
+use rand::Rng;
+use serde::Deserialize;
+use std::{collections::HashMap, net::SocketAddr};
+
+mod binance;
+mod bybit;
+mod coinbase;
+mod kraken;
+mod okx;
+
+/// Settings read from `MOCK_*` environment variables
+#[derive(Debug, Deserialize, Clone)]
+pub struct MockConfig {
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Time between ticks of every subscribed stream
+    #[serde(default = "default_tick_ms")]
+    pub tick_ms: u64,
+    /// Drops every connection without a close frame after this many seconds,
+    /// 0 keeps connections open
+    #[serde(default)]
+    pub disconnect_secs: u64,
+    /// Every n-th market data frame is sent malformed, 0 never
+    #[serde(default)]
+    pub malformed_every: u64,
+}
+
+fn default_port() -> u16 {
+    9090
+}
+fn default_tick_ms() -> u64 {
+    500
+}
+
+/// (base, quote, starting price) of the listed markets
+const MARKETS: [(&str, &str, f64); 3] = [
+    ("BTC", "USDT", 60_000.0),
+    ("ETH", "USDT", 3_000.0),
+    ("SOL", "USDT", 150.0),
+];
+
+/// Random walk of the market prices, one per connection
+pub struct Prices {
+    prices: HashMap<String, f64>,
+}
+
+impl Prices {
+    /// Markets keyed by `symbol(base, quote)`
+    pub fn new(symbol: fn(&str, &str) -> String) -> Self {
+        Prices {
+            prices: MARKETS
+                .iter()
+                .map(|(base, quote, price)| (symbol(base, quote), *price))
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.prices.contains_key(symbol)
+    }
+
+    /// Moves the price by up to 0.1% and returns it
+    pub fn tick(&mut self, symbol: &str) -> f64 {
+        let price = self.prices.get_mut(symbol).expect("Unknown market");
+        *price *= 1.0 + rand::thread_rng().gen_range(-0.001..0.001);
+
+        *price
+    }
+}
+
+/// Counts market data frames and corrupts every `malformed_every`-th one
+pub struct Faults {
+    malformed_every: u64,
+    frames: u64,
+}
+
+impl Faults {
+    pub fn new(config: &MockConfig) -> Self {
+        Faults {
+            malformed_every: config.malformed_every,
+            frames: 0,
+        }
+    }
+
+    pub fn frame(&mut self, frame: String) -> Message {
+        self.frames += 1;
+
+        if self.malformed_every > 0 && self.frames % self.malformed_every == 0 {
+            info!("Sending malformed frame");
+            return Message::Text(frame[..frame.len() / 2].to_string());
+        }
+
+        Message::Text(frame)
+    }
+}
+
+pub fn markets() -> impl Iterator<Item = (&'static str, &'static str)> {
+    MARKETS.iter().map(|(base, quote, _)| (*base, *quote))
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    env_logger::init();
+
+    let config: MockConfig = config::Config::builder()
+        .add_source(Environment::with_prefix("MOCK"))
+        .build()
+        .and_then(|config| config.try_deserialize())
+        .expect("Failed to load configuration");
+
+    let addr: SocketAddr = format!("0.0.0.0:{}", config.port)
+        .parse()
+        .expect("Invalid address format");
+
+    let app = Router::new()
+        .nest("/binance", binance::routes())
+        .nest("/coinbase", coinbase::routes())
+        .nest("/kraken", kraken::routes())
+        .nest("/okx", okx::routes())
+        .nest("/bybit", bybit::routes())
+        .with_state(config);
+
+    info!("Mock exchange listening on {}", addr);
+
+    Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .expect("Server error");
+}
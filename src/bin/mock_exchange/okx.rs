@@ -0,0 +1,172 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use tokio::time::{interval, sleep_until, Duration, Instant};
+
+use crate::{markets, Faults, MockConfig, Prices};
+
+#[derive(Deserialize, Debug)]
+struct OpRequest {
+    op: String,
+    #[serde(default)]
+    args: Vec<Arg>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Arg {
+    channel: String,
+    inst_id: String,
+}
+
+pub fn routes() -> Router<MockConfig> {
+    Router::new()
+        .route("/api/v5/public/instruments", get(instruments))
+        .route("/ws/v5/public", get(ws_handler))
+}
+
+fn symbol(base: &str, quote: &str) -> String {
+    format!("{}-{}", base, quote)
+}
+
+async fn instruments() -> Json<Value> {
+    let instruments = markets()
+        .map(|(base, quote)| {
+            json!({
+                "instId": symbol(base, quote),
+                "baseCcy": base,
+                "quoteCcy": quote,
+                "state": "live",
+                "tickSz": "0.01",
+                "lotSz": "0.0001",
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Json(json!({ "code": "0", "msg": "", "data": instruments }))
+}
+
+async fn ws_handler(ws: WebSocketUpgrade, State(config): State<MockConfig>) -> Response {
+    ws.on_upgrade(|socket| feed(socket, config))
+}
+
+/// Public WebSocket, sends the `tickers` channel of the subscribed
+/// instruments and answers text pings
+async fn feed(socket: WebSocket, config: MockConfig) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut prices = Prices::new(symbol);
+    let mut faults = Faults::new(&config);
+    let mut inst_ids = BTreeSet::new();
+
+    let mut tick_timer = interval(Duration::from_millis(config.tick_ms));
+    let disconnect_at = Instant::now() + Duration::from_secs(config.disconnect_secs);
+
+    info!("OKX client connected");
+
+    loop {
+        let frames: Vec<Message> = tokio::select! {
+            message = receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                if text == "ping" {
+                    vec![Message::Text("pong".to_string())]
+                } else {
+                    let request: OpRequest = match serde_json::from_str(&text) {
+                        Ok(request) => request,
+                        Err(err) => {
+                            warn!("Invalid OKX request: {}", err);
+                            continue;
+                        }
+                    };
+
+                    request
+                        .args
+                        .iter()
+                        .filter(|arg| arg.channel == "tickers")
+                        .map(|arg| {
+                            match request.op.as_str() {
+                                "subscribe" => {
+                                    inst_ids.insert(arg.inst_id.clone());
+                                }
+                                "unsubscribe" => {
+                                    inst_ids.remove(&arg.inst_id);
+                                }
+                                op => warn!("Unknown OKX op: {}", op),
+                            }
+
+                            Message::Text(
+                                json!({
+                                    "event": request.op,
+                                    "arg": { "channel": arg.channel, "instId": arg.inst_id },
+                                })
+                                .to_string(),
+                            )
+                        })
+                        .collect()
+                }
+            }
+            _ = tick_timer.tick() => {
+                let ts = Utc::now().timestamp_millis().to_string();
+
+                inst_ids
+                    .iter()
+                    .filter_map(|inst_id| {
+                        if !prices.contains(inst_id) {
+                            return None;
+                        }
+
+                        let price = prices.tick(inst_id);
+                        let data = json!({
+                            "instId": inst_id,
+                            "last": format!("{:.2}", price),
+                            "open24h": format!("{:.2}", price * 0.99),
+                            "high24h": format!("{:.2}", price * 1.02),
+                            "low24h": format!("{:.2}", price * 0.98),
+                            "vol24h": "1000.00",
+                            "volCcy24h": format!("{:.2}", price * 1000.0),
+                            "bidPx": format!("{:.2}", price * 0.9999),
+                            "askPx": format!("{:.2}", price * 1.0001),
+                            "ts": ts,
+                        });
+
+                        Some(faults.frame(
+                            json!({
+                                "arg": { "channel": "tickers", "instId": inst_id },
+                                "data": [data],
+                            })
+                            .to_string(),
+                        ))
+                    })
+                    .collect()
+            }
+            _ = sleep_until(disconnect_at), if config.disconnect_secs > 0 => {
+                info!("Dropping OKX client");
+                return;
+            }
+        };
+
+        for frame in frames {
+            if sender.send(frame).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    info!("OKX client disconnected");
+}
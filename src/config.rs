@@ -8,8 +8,119 @@ pub struct Config {
     pub server_port: u16,
     #[serde(default = "default_binance_ws_url")]
     pub binance_ws_url: String,
+    #[serde(default = "default_binance_api_url")]
+    pub binance_api_url: String,
     #[serde(default = "default_coinbase_ws_url")]
     pub coinbase_ws_url: String,
+    #[serde(default = "default_coinbase_api_url")]
+    pub coinbase_api_url: String,
+    #[serde(default = "default_kraken_ws_url")]
+    pub kraken_ws_url: String,
+    #[serde(default = "default_kraken_api_url")]
+    pub kraken_api_url: String,
+    #[serde(default = "default_okx_ws_url")]
+    pub okx_ws_url: String,
+    #[serde(default = "default_okx_api_url")]
+    pub okx_api_url: String,
+    #[serde(default = "default_bybit_ws_url")]
+    pub bybit_ws_url: String,
+    #[serde(default = "default_bybit_api_url")]
+    pub bybit_api_url: String,
+    /// Comma separated venues to connect to: binance, coinbase, kraken, okx
+    /// and bybit
+    #[serde(default = "default_exchanges")]
+    pub exchanges: String,
+    /// 0 fetches the symbols once at startup
+    #[serde(default = "default_symbol_refresh_secs")]
+    pub symbol_refresh_secs: u64,
+    #[serde(default = "default_reconnect_initial_delay_ms")]
+    pub reconnect_initial_delay_ms: u64,
+    #[serde(default = "default_reconnect_multiplier")]
+    pub reconnect_multiplier: f64,
+    #[serde(default = "default_reconnect_max_delay_ms")]
+    pub reconnect_max_delay_ms: u64,
+    #[serde(default = "default_reconnect_jitter")]
+    pub reconnect_jitter: f64,
+    /// Reconnects and restarts before a feed is marked dead, 0 means it keeps
+    /// reconnecting forever
+    #[serde(default)]
+    pub reconnect_max_attempts: u32,
+    /// 0 only sends the pings venues require
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+    /// 0 keeps silent connections open
+    #[serde(default = "default_stale_timeout_secs")]
+    pub stale_timeout_secs: u64,
+    /// Comma separated `BASE-QUOTE` symbols to build order books for
+    #[serde(default = "default_order_book_symbols")]
+    pub order_book_symbols: String,
+    #[serde(default = "default_order_book_depth")]
+    pub order_book_depth: usize,
+    /// Comma separated `BASE-QUOTE` symbols to ingest trades for
+    #[serde(default = "default_trade_symbols")]
+    pub trade_symbols: String,
+    /// `trade` or `aggTrade`
+    #[serde(default = "default_binance_trade_stream")]
+    pub binance_trade_stream: String,
+    /// `carry` fills intervals without ticks with the previous close,
+    /// anything else leaves them empty
+    #[serde(default = "default_candle_gap_fill")]
+    pub candle_gap_fill: String,
+    /// Directory to record raw feed frames to, recording is off when empty
+    #[serde(default)]
+    pub record_dir: String,
+    /// Journal to replay instead of connecting to the venues
+    #[serde(default)]
+    pub replay_file: String,
+    /// 1 replays at the original speed, 0 as fast as possible
+    #[serde(default = "default_replay_speed")]
+    pub replay_speed: f64,
+    /// Number of undecodable frames kept for `/admin/dead_letters`
+    #[serde(default = "default_dead_letter_capacity")]
+    pub dead_letter_capacity: usize,
+    /// Globs of the canonical `BASE-QUOTE` symbols to subscribe to, e.g.
+    /// `*-USDT,coinbase:BTC-*`, empty for all symbols
+    #[serde(default)]
+    pub symbol_include: String,
+    /// Globs of symbols to skip, same format as `symbol_include`
+    #[serde(default)]
+    pub symbol_exclude: String,
+    /// Quote assets to subscribe to, e.g. `USDT,USDC,coinbase:USD`, empty for
+    /// all quote assets
+    #[serde(default)]
+    pub symbol_quotes: String,
+    /// JSON file declaring venues handled by the generic connector, see
+    /// `venues.example.json`
+    #[serde(default)]
+    pub venues_file: String,
+    /// Seconds a feed has to be down before its tickers are polled over REST,
+    /// 0 disables the fallback
+    #[serde(default = "default_fallback_after_secs")]
+    pub fallback_after_secs: u64,
+    /// How composite prices combine the sources: `median`, `volume` or
+    /// `weights`
+    #[serde(default = "default_composite_method")]
+    pub composite_method: String,
+    /// Weights of the sources for the `weights` method, e.g.
+    /// `binance:2,coinbase:1`, sources without a weight are left out
+    #[serde(default)]
+    pub composite_weights: String,
+    /// Tickers older than this are left out of composite prices
+    #[serde(default = "default_composite_max_age_secs")]
+    pub composite_max_age_secs: u64,
+    /// Sources further than this from the median are left out of composite
+    /// prices, 0.01 is 1%
+    #[serde(default = "default_composite_max_deviation")]
+    pub composite_max_deviation: f64,
+    /// Sources needed for a composite price, 0 disables composite prices
+    #[serde(default = "default_composite_min_sources")]
+    pub composite_min_sources: usize,
+    /// Tickers older than this are not used for cross rates
+    #[serde(default = "default_cross_rate_max_age_secs")]
+    pub cross_rate_max_age_secs: u64,
+    /// Most conversions a cross rate may chain
+    #[serde(default = "default_cross_rate_max_hops")]
+    pub cross_rate_max_hops: usize,
     #[serde(default = "default_rust_log")]
     pub rust_log: String,
     pub database_url: String,
@@ -25,17 +136,127 @@ impl Config {
 
         Ok(config)
     }
+
+    pub fn exchange_enabled(&self, name: &str) -> bool {
+        parse_list(&self.exchanges).contains(&name.to_uppercase())
+    }
+
+    pub fn order_book_symbols(&self) -> Vec<String> {
+        parse_list(&self.order_book_symbols)
+    }
+
+    pub fn trade_symbols(&self) -> Vec<String> {
+        parse_list(&self.trade_symbols)
+    }
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_uppercase())
+        .filter(|item| !item.is_empty())
+        .collect()
 }
 
 fn default_server_port() -> u16 {
     8080
 }
 fn default_binance_ws_url() -> String {
-    "wss://stream.binance.com:9443/ws".to_string()
+    "wss://stream.binance.com:9443/stream".to_string()
+}
+fn default_binance_api_url() -> String {
+    "https://api.binance.com".to_string()
 }
 fn default_coinbase_ws_url() -> String {
     "wss://ws-feed.exchange.coinbase.com".to_string()
 }
+fn default_coinbase_api_url() -> String {
+    "https://api.exchange.coinbase.com".to_string()
+}
+fn default_kraken_ws_url() -> String {
+    "wss://ws.kraken.com/v2".to_string()
+}
+fn default_kraken_api_url() -> String {
+    "https://api.kraken.com".to_string()
+}
+fn default_okx_ws_url() -> String {
+    "wss://ws.okx.com:8443/ws/v5/public".to_string()
+}
+fn default_okx_api_url() -> String {
+    "https://www.okx.com".to_string()
+}
+fn default_bybit_ws_url() -> String {
+    "wss://stream.bybit.com/v5/public/spot".to_string()
+}
+fn default_bybit_api_url() -> String {
+    "https://api.bybit.com".to_string()
+}
+fn default_exchanges() -> String {
+    "binance,coinbase,kraken,okx,bybit".to_string()
+}
+fn default_symbol_refresh_secs() -> u64 {
+    300
+}
+fn default_reconnect_initial_delay_ms() -> u64 {
+    1000
+}
+fn default_reconnect_multiplier() -> f64 {
+    2.0
+}
+fn default_reconnect_max_delay_ms() -> u64 {
+    60_000
+}
+fn default_reconnect_jitter() -> f64 {
+    0.5
+}
+fn default_heartbeat_interval_secs() -> u64 {
+    15
+}
+fn default_stale_timeout_secs() -> u64 {
+    30
+}
+fn default_order_book_symbols() -> String {
+    "BTC-USDT,ETH-USDT".to_string()
+}
+fn default_order_book_depth() -> usize {
+    10
+}
+fn default_trade_symbols() -> String {
+    "BTC-USDT,ETH-USDT".to_string()
+}
+fn default_binance_trade_stream() -> String {
+    "trade".to_string()
+}
+fn default_candle_gap_fill() -> String {
+    "carry".to_string()
+}
+fn default_replay_speed() -> f64 {
+    1.0
+}
+fn default_dead_letter_capacity() -> usize {
+    1000
+}
+fn default_fallback_after_secs() -> u64 {
+    60
+}
+fn default_composite_method() -> String {
+    "median".to_string()
+}
+fn default_composite_max_age_secs() -> u64 {
+    30
+}
+fn default_composite_max_deviation() -> f64 {
+    0.01
+}
+fn default_composite_min_sources() -> usize {
+    2
+}
+fn default_cross_rate_max_age_secs() -> u64 {
+    60
+}
+fn default_cross_rate_max_hops() -> usize {
+    3
+}
 fn default_rust_log() -> String {
     "debug".to_string()
 }
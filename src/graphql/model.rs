@@ -1,8 +1,17 @@
 use async_graphql::{Context, EmptySubscription, Object, Schema, SimpleObject};
+use chrono::Utc;
 use log::{info, warn};
+use rust_decimal::Decimal;
 
-use crate::redis_connection;
-use crate::services::coinbase::fetch_coinbase_price;
+use crate::services::assets::Asset;
+use crate::services::candles::{fetch_candles, Candle};
+use crate::services::cross_rates::{CrossRate, CrossRates};
+use crate::services::latency::LatencyStats;
+use crate::services::order_book::OrderBookView;
+use crate::services::price_history::{fetch_prices, PricePoint};
+use crate::services::supervisor::FeedStatus;
+use crate::services::symbols::SymbolInfo;
+use crate::services::ws_message::WsMessage;
 
 pub type ServiceSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 pub struct QueryRoot;
@@ -30,35 +39,226 @@ impl QueryRoot {
 
         Some(row)
     }
+
+    /// Latest ticker with 24h statistics streamed from the given source
+    async fn ticker(
+        &self,
+        ctx: &Context<'_>,
+        source: String,
+        base: String,
+        quote: String,
+    ) -> Option<WsMessage> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+        let source = source.to_lowercase();
+        // Codes of the source first, then of any venue
+        let base = state
+            .assets
+            .resolve(&state.assets.canonical(&source, &base));
+        let quote = state
+            .assets
+            .resolve(&state.assets.canonical(&source, &quote));
+
+        state.tickers.get(&source, &base, &quote)
+    }
+
+    /// Latest tickers of the same instrument on every source. Venue specific
+    /// asset codes (e.g. `XBT`) are accepted.
+    async fn instrument(&self, ctx: &Context<'_>, base: String, quote: String) -> Vec<WsMessage> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+
+        state
+            .tickers
+            .instrument(&state.assets.resolve(&base), &state.assets.resolve(&quote))
+    }
+
+    /// Latency histograms of every source (or one) since startup
+    async fn latency(&self, ctx: &Context<'_>, source: Option<String>) -> Vec<LatencyStats> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+        let source = source.map(|source| source.to_lowercase());
+
+        state.latency.stats(source.as_deref())
+    }
+
+    /// State of every feed connection
+    async fn feeds(&self, ctx: &Context<'_>) -> Vec<FeedStatus> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+
+        state.supervisor.feeds()
+    }
+
+    /// Canonical assets with their venue aliases
+    async fn assets(&self, ctx: &Context<'_>) -> Vec<Asset> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+
+        state.assets.all()
+    }
+
+    /// Top levels of an order book listed in `ORDER_BOOK_SYMBOLS`
+    async fn order_book(
+        &self,
+        ctx: &Context<'_>,
+        source: String,
+        base: String,
+        quote: String,
+        depth: Option<usize>,
+    ) -> Option<OrderBookView> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+        let symbol = format!("{}-{}", base.to_uppercase(), quote.to_uppercase());
+
+        state.order_books.top(
+            &source.to_lowercase(),
+            &symbol,
+            depth.unwrap_or(state.config.order_book_depth),
+        )
+    }
+
+    /// Closed candles of an interval (`1m`, `5m`, `1h` or `1d`), newest first
+    async fn candles(
+        &self,
+        ctx: &Context<'_>,
+        source: String,
+        base: String,
+        quote: String,
+        interval: String,
+        limit: Option<i64>,
+    ) -> async_graphql::Result<Vec<Candle>> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+        let symbol = format!("{}-{}", base.to_uppercase(), quote.to_uppercase());
+
+        let candles = fetch_candles(
+            &state.db_connection,
+            &source.to_lowercase(),
+            &symbol,
+            &interval,
+            limit.unwrap_or(100),
+        )
+        .await?;
+
+        Ok(candles)
+    }
+
+    /// Published venue prices as saved in `ticker_prices`, newest first.
+    /// Composite prices are not saved.
+    async fn price_history(
+        &self,
+        ctx: &Context<'_>,
+        source: String,
+        base: String,
+        quote: String,
+        limit: Option<i64>,
+    ) -> async_graphql::Result<Vec<PricePoint>> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+        let prices = fetch_prices(
+            &state.db_connection,
+            &source.to_lowercase(),
+            &base.to_uppercase(),
+            &quote.to_uppercase(),
+            limit.unwrap_or(100),
+        )
+        .await?;
+
+        Ok(prices)
+    }
+
+    /// Candle of the current interval, updated with every tick
+    async fn live_candle(
+        &self,
+        ctx: &Context<'_>,
+        source: String,
+        base: String,
+        quote: String,
+        interval: String,
+    ) -> Option<Candle> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+        let symbol = format!("{}-{}", base.to_uppercase(), quote.to_uppercase());
+
+        state
+            .candles
+            .live(&source.to_lowercase(), &symbol, &interval)
+    }
+
+    /// Markets listed by the venues, optionally of a single source
+    async fn symbols(&self, ctx: &Context<'_>, source: Option<String>) -> Vec<SymbolInfo> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+        let source = source.map(|source| source.to_lowercase());
+
+        state.symbols.all(source.as_deref())
+    }
+
+    async fn tickers(&self, ctx: &Context<'_>, source: Option<String>) -> Vec<WsMessage> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+
+        state
+            .tickers
+            .all()
+            .into_iter()
+            .filter(|ticker| match &source {
+                Some(source) => ticker.get_source() == source.to_lowercase(),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Same rate as the `tickerPrice` mutation along with the tickers it was
+    /// converted through and the age of the oldest one, empty when there is
+    /// no live rate
+    async fn cross_rate(
+        &self,
+        ctx: &Context<'_>,
+        base: String,
+        quote: String,
+    ) -> Option<CrossRate> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+
+        cross_rate(state, &base, &quote)
+    }
 }
 
 #[Object]
 impl MutationRoot {
-    async fn ticker_price(&self, base: String, quote: String) -> String {
-        // TODO: check how to use directives to uppercase base and quote
-        let redis_key = format!("{}-{}", base.to_uppercase(), quote.to_uppercase());
-
-        let redis_value = redis::cmd("GET")
-            .arg(&redis_key)
-            .query(&mut *redis_connection::get_redis_connection());
-
-        match redis_value {
-            Ok(value) => {
-                info!("Cache hit: {}", value);
-                return value;
-            }
-            Err(err) => {
-                warn!("Cache hit missed key: {}", redis_key);
-                warn!("Error: {}", err);
-                match fetch_coinbase_price(base.as_str(), quote.as_str()).await {
-                    Ok(ticker_data) => ticker_data.amount,
-                    Err(err) => {
-                        warn!("Error retrieving Coinbase amount: {}", err);
-                        // TODO: should return a GraphQL error
-                        String::from("Failed to fetch ticker price")
-                    }
-                }
-            }
+    /// Price of `base` in `quote` from the live tickers, converted through
+    /// other assets when no source lists the pair (e.g. SOL-EUR as SOL-USDT
+    /// and USDT-EUR)
+    async fn ticker_price(
+        &self,
+        ctx: &Context<'_>,
+        base: String,
+        quote: String,
+    ) -> async_graphql::Result<Decimal> {
+        let state = ctx.data::<crate::AppContext>().unwrap();
+
+        match cross_rate(state, &base, &quote) {
+            Some(cross_rate) => Ok(cross_rate.price),
+            None => Err("Failed to fetch ticker price".into()),
+        }
+    }
+}
+
+/// Live rate of `base` in `quote` and the tickers it was converted through
+fn cross_rate(state: &crate::AppContext, base: &str, quote: &str) -> Option<CrossRate> {
+    let base = state.assets.resolve(base);
+    let quote = state.assets.resolve(quote);
+
+    let tickers = state.tickers.all();
+    let cross_rates = CrossRates::new(
+        &tickers,
+        Utc::now().timestamp_millis(),
+        state.config.cross_rate_max_age_secs as i64 * 1000,
+    );
+
+    match cross_rates.rate(&base, &quote, state.config.cross_rate_max_hops) {
+        Some(cross_rate) => {
+            info!(
+                "{}-{} rate from {} tickers",
+                base,
+                quote,
+                cross_rate.path.len()
+            );
+            Some(cross_rate)
+        }
+        None => {
+            warn!("No live rate for {}-{}", base, quote);
+            None
         }
     }
 }
@@ -0,0 +1,36 @@
+use sqlx::SqlitePool;
+use tokio::sync::broadcast;
+use tungstenite::Message;
+
+use crate::config::Config;
+use crate::services::{
+    assets::AssetRegistry, candles::CandleStore, dead_letters::DeadLetterStore,
+    latency::LatencyTracker, order_book::OrderBookStore, price_history::PriceHistory,
+    recorder::FeedRecorder, supervisor::Supervisor, symbols::SymbolRegistry,
+    ticker_store::TickerStore,
+};
+
+pub mod api;
+pub mod config;
+pub mod graphql;
+pub mod services;
+
+#[derive(Clone)]
+pub struct AppContext {
+    pub db_connection: SqlitePool,
+    pub config: Config,
+    pub ticker_tx: broadcast::Sender<Message>,
+    pub tickers: TickerStore,
+    pub book_tx: broadcast::Sender<Message>,
+    pub order_books: OrderBookStore,
+    pub trade_tx: broadcast::Sender<Message>,
+    pub candles: CandleStore,
+    pub candle_tx: broadcast::Sender<Message>,
+    pub prices: PriceHistory,
+    pub recorder: Option<FeedRecorder>,
+    pub symbols: SymbolRegistry,
+    pub assets: AssetRegistry,
+    pub dead_letters: DeadLetterStore,
+    pub supervisor: Supervisor,
+    pub latency: LatencyTracker,
+}
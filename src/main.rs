@@ -3,31 +3,43 @@ use axum::{routing::get, Extension, Router, Server};
 use dotenv::dotenv;
 use futures_util::TryFutureExt;
 use log::{error, warn};
-use services::binance;
-use sqlx::{self, sqlite::SqlitePoolOptions, SqlitePool};
-use std::net::SocketAddr;
-use tokio::sync::broadcast;
+use sqlx::{self, sqlite::SqlitePoolOptions};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::{
+    sync::{broadcast, watch},
+    time::Duration,
+};
 use tungstenite::Message;
 
-use crate::api::routes::{graphql_handler, graphql_playground, health, root};
-use crate::config::Config;
-use crate::graphql::{MutationRoot, QueryRoot};
-use crate::services::{
-    binance::subscribe_binance_ticker, coinbase::subscribe_coinbase_ticker, redis_connection,
+use rust_ticker_server::api::routes::{
+    clear_dead_letters, get_dead_letters, graphql_handler, graphql_playground, health, root,
+};
+use rust_ticker_server::config::Config;
+use rust_ticker_server::graphql::{MutationRoot, QueryRoot};
+use rust_ticker_server::services::{
+    assets::AssetRegistry,
+    binance::{self, BinanceBookConnector, BinanceConnector, BinanceTradeConnector},
+    bybit::BybitConnector,
+    candles::{run_candle_engine, CandleStore},
+    coinbase::{CoinbaseBookConnector, CoinbaseConnector, CoinbaseTradeConnector},
+    composite::run_composite,
+    connector::{run_shards, watch_symbols, ExchangeConnector},
+    dead_letters::DeadLetterStore,
+    generic::{load_venues, GenericConnector, VenueSpec},
+    kraken::KrakenConnector,
+    latency::LatencyTracker,
+    okx::OkxConnector,
+    order_book::OrderBookStore,
+    price_history::{run_price_writer, PriceHistory},
+    reconnect::ReconnectPolicy,
+    recorder::FeedRecorder,
+    replay::run_replay,
+    supervisor::Supervisor,
+    symbols::{SymbolFilter, SymbolRegistry},
+    ticker_store::TickerStore,
     websocket::websocket_handler,
 };
-
-mod api;
-mod config;
-mod graphql;
-mod services;
-
-#[derive(Clone)]
-pub struct AppContext {
-    pub db_connection: SqlitePool,
-    pub config: Config,
-    pub ticker_tx: broadcast::Sender<Message>,
-}
+use rust_ticker_server::AppContext;
 
 #[tokio::main]
 async fn main() {
@@ -41,12 +53,46 @@ async fn main() {
         .await
         .unwrap();
 
+    let assets = AssetRegistry::load(&pool)
+        .await
+        .expect("Failed to load currencies");
+
+    let dead_letters = DeadLetterStore::new(config.dead_letter_capacity);
+    let symbol_filter = SymbolFilter::new(&config);
+
     let (ticker_tx, _rx) = broadcast::channel::<Message>(100);
+    let (book_tx, _) = broadcast::channel::<Message>(100);
+    let (trade_tx, _) = broadcast::channel::<Message>(100);
+    let (candle_tx, _) = broadcast::channel::<Message>(100);
+    let (candles, candle_receiver) = CandleStore::new();
+    let (prices, price_receiver) = PriceHistory::new();
+
+    // Replays are not recorded again
+    let recorder = if config.record_dir.is_empty() || !config.replay_file.is_empty() {
+        None
+    } else {
+        Some(FeedRecorder::start(&config.record_dir).expect("Failed to start feed recorder"))
+    };
+
+    let symbols = SymbolRegistry::new(assets.clone(), symbol_filter, recorder.clone());
 
     let app_context = AppContext {
         db_connection: pool,
         config,
         ticker_tx,
+        tickers: TickerStore::default(),
+        book_tx,
+        order_books: OrderBookStore::default(),
+        trade_tx,
+        candles,
+        candle_tx,
+        prices,
+        recorder,
+        symbols,
+        assets,
+        dead_letters,
+        supervisor: Supervisor::default(),
+        latency: LatencyTracker::default(),
     };
 
     let addr: SocketAddr = format!("0.0.0.0:{}", app_context.config.server_port)
@@ -61,27 +107,24 @@ async fn main() {
         .route("/", get(root))
         .route("/ws", get(websocket_handler))
         .route("/health", get(health))
+        .route(
+            "/admin/dead_letters",
+            get(get_dead_letters).delete(clear_dead_letters),
+        )
         .route("/graphql", get(graphql_playground).post(graphql_handler))
         .with_state(app_context.clone())
         .layer(Extension(gql_schema));
 
-    // Spinning up a separate task to subscribe to Coinbase ticker
-    let app_context_cl = app_context.clone();
-    tokio::task::spawn(async move {
-        subscribe_coinbase_ticker(app_context_cl)
-            .unwrap_or_else(|err| warn!("Connecting to socket failed: {}", err))
-            .await
-    });
-
-    let chunked_streams = binance::get_chunked_ws_streams().await.unwrap();
+    tokio::task::spawn(run_candle_engine(app_context.clone(), candle_receiver));
+    tokio::task::spawn(run_price_writer(app_context.clone(), price_receiver));
+    if app_context.config.composite_min_sources > 0 {
+        tokio::task::spawn(run_composite(app_context.clone()));
+    }
 
-    for stream in chunked_streams {
-        let app_context_cl = app_context.clone();
-        tokio::task::spawn(async move {
-            subscribe_binance_ticker(app_context_cl, &stream)
-                .unwrap_or_else(|err| warn!("Connecting to socket failed: {}", err))
-                .await
-        });
+    if app_context.config.replay_file.is_empty() {
+        spawn_feeds(&app_context);
+    } else {
+        spawn_replay(&app_context);
     }
 
     if let Err(e) = Server::bind(&addr).serve(app.into_make_service()).await {
@@ -89,3 +132,162 @@ async fn main() {
         std::process::exit(1);
     }
 }
+
+/// Connects to every venue. Symbols are fetched in the background, so that an
+/// unreachable venue doesn't hold up the others.
+fn spawn_feeds(app_context: &AppContext) {
+    let config = &app_context.config;
+    let retry_policy = ReconnectPolicy::new(config);
+    // 0 fetches the symbols once
+    let refresh_interval = match config.symbol_refresh_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    if config.exchange_enabled("coinbase") {
+        // Spinning up a separate task to subscribe to Coinbase ticker
+        let coinbase_connector = Arc::new(CoinbaseConnector::new(config, &app_context.symbols));
+        let coinbase_symbols = watch_symbols(
+            coinbase_connector.clone(),
+            retry_policy.clone(),
+            refresh_interval,
+        );
+        spawn_connector(app_context, coinbase_connector, coinbase_symbols);
+    }
+
+    if config.exchange_enabled("kraken") {
+        let kraken_connector = Arc::new(KrakenConnector::new(config, &app_context.symbols));
+        let kraken_symbols = watch_symbols(kraken_connector.clone(), retry_policy.clone(), None);
+        spawn_connector(app_context, kraken_connector, kraken_symbols);
+    }
+
+    if config.exchange_enabled("okx") {
+        let okx_connector = Arc::new(OkxConnector::new(config, &app_context.symbols));
+        let okx_symbols = watch_symbols(okx_connector.clone(), retry_policy.clone(), None);
+        spawn_connector(app_context, okx_connector, okx_symbols);
+    }
+
+    if config.exchange_enabled("bybit") {
+        let bybit_connector = Arc::new(BybitConnector::new(config, &app_context.symbols));
+        let bybit_symbols = watch_symbols(bybit_connector.clone(), retry_policy.clone(), None);
+        spawn_connector(app_context, bybit_connector, bybit_symbols);
+    }
+
+    if config.exchange_enabled("binance") {
+        let binance_connector = Arc::new(BinanceConnector::new(config, &app_context.symbols));
+        let binance_symbols = watch_symbols(
+            binance_connector.clone(),
+            retry_policy.clone(),
+            refresh_interval,
+        );
+
+        let app_context_cl = app_context.clone();
+        tokio::task::spawn(run_shards(
+            binance_symbols,
+            binance::STREAMS_PER_CONNECTION,
+            move |shard| spawn_connector(&app_context_cl, binance_connector.clone(), shard),
+        ));
+    }
+
+    for venue in venues(config) {
+        let generic_connector = Arc::new(GenericConnector::new(venue, &app_context.symbols));
+        let generic_symbols = watch_symbols(generic_connector.clone(), retry_policy.clone(), None);
+        spawn_connector(app_context, generic_connector, generic_symbols);
+    }
+
+    let order_book_symbols = config.order_book_symbols();
+    if !order_book_symbols.is_empty() {
+        if config.exchange_enabled("coinbase") {
+            spawn_connector(
+                app_context,
+                Arc::new(CoinbaseBookConnector::new(config)),
+                watch::channel(order_book_symbols.clone()).1,
+            );
+        }
+        if config.exchange_enabled("binance") {
+            spawn_connector(
+                app_context,
+                Arc::new(BinanceBookConnector::new(config)),
+                watch::channel(order_book_symbols).1,
+            );
+        }
+    }
+
+    let trade_symbols = config.trade_symbols();
+    if !trade_symbols.is_empty() {
+        if config.exchange_enabled("coinbase") {
+            app_context.candles.use_trades("coinbase", &trade_symbols);
+            spawn_connector(
+                app_context,
+                Arc::new(CoinbaseTradeConnector::new(config)),
+                watch::channel(trade_symbols.clone()).1,
+            );
+        }
+        if config.exchange_enabled("binance") {
+            app_context.candles.use_trades("binance", &trade_symbols);
+            spawn_connector(
+                app_context,
+                Arc::new(BinanceTradeConnector::new(config)),
+                watch::channel(trade_symbols).1,
+            );
+        }
+    }
+}
+
+/// Plays back `REPLAY_FILE` instead of connecting to the venues
+fn spawn_replay(app_context: &AppContext) {
+    let config = &app_context.config;
+    let mut connectors: Vec<Arc<dyn ExchangeConnector>> = vec![
+        Arc::new(CoinbaseConnector::new(config, &app_context.symbols)),
+        Arc::new(KrakenConnector::new(config, &app_context.symbols)),
+        Arc::new(OkxConnector::new(config, &app_context.symbols)),
+        Arc::new(BybitConnector::new(config, &app_context.symbols)),
+        Arc::new(BinanceConnector::new(config, &app_context.symbols)),
+        Arc::new(CoinbaseBookConnector::new(config)),
+        Arc::new(BinanceBookConnector::new(config)),
+        Arc::new(CoinbaseTradeConnector::new(config)),
+        Arc::new(BinanceTradeConnector::new(config)),
+    ];
+    // Same candle sources as the recording, as long as the config matches
+    let trade_symbols = config.trade_symbols();
+    for source in ["coinbase", "binance"] {
+        if config.exchange_enabled(source) {
+            app_context.candles.use_trades(source, &trade_symbols);
+        }
+    }
+    for venue in venues(config) {
+        connectors.push(Arc::new(GenericConnector::new(venue, &app_context.symbols)));
+    }
+
+    tokio::task::spawn(
+        run_replay(
+            app_context.clone(),
+            connectors,
+            config.replay_file.clone(),
+            config.replay_speed,
+        )
+        .unwrap_or_else(|err| warn!("Replay failed: {}", err)),
+    );
+}
+
+/// Venues of `VENUES_FILE`, none when it can't be loaded
+fn venues(config: &Config) -> Vec<VenueSpec> {
+    if config.venues_file.is_empty() {
+        return vec![];
+    }
+
+    load_venues(&config.venues_file).unwrap_or_else(|err| {
+        error!("No venues from {}: {:#}", config.venues_file, err);
+        vec![]
+    })
+}
+
+fn spawn_connector(
+    app_context: &AppContext,
+    connector: Arc<dyn ExchangeConnector>,
+    symbols: watch::Receiver<Vec<String>>,
+) {
+    app_context
+        .supervisor
+        .spawn(app_context.clone(), connector, symbols);
+}
@@ -0,0 +1,130 @@
+use async_graphql::SimpleObject;
+use eyre::Result;
+use log::warn;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+};
+
+/// Canonical asset from the `currencies` table
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct Asset {
+    pub symbol: String,
+    pub name: Option<String>,
+    /// Codes the venues use for the asset, as `source:alias`
+    pub aliases: Vec<String>,
+}
+
+/// Maps the asset codes of every venue to the canonical ones, loaded from the
+/// canonical `currencies` and the `currency_aliases` tables at startup
+#[derive(Clone, Default)]
+pub struct AssetRegistry {
+    assets: Arc<Vec<Asset>>,
+    // (source, alias) -> canonical symbol
+    aliases: Arc<HashMap<(String, String), String>>,
+    // alias -> canonical symbol, for aliases which mean the same asset on
+    // every venue
+    shared_aliases: Arc<HashMap<String, String>>,
+}
+
+impl AssetRegistry {
+    pub async fn load(db_connection: &SqlitePool) -> Result<Self> {
+        let currencies =
+            sqlx::query!("SELECT name, symbol FROM currencies WHERE canonical ORDER BY id")
+                .fetch_all(db_connection)
+                .await?;
+
+        let alias_rows = sqlx::query!(
+            "SELECT providers.name AS provider, currency_aliases.alias, currencies.symbol
+             FROM currency_aliases
+             JOIN providers ON providers.id = currency_aliases.provider_id
+             JOIN currencies ON currencies.id = currency_aliases.currency_id"
+        )
+        .fetch_all(db_connection)
+        .await?;
+
+        let mut aliases = HashMap::new();
+        for row in alias_rows {
+            if let (Some(provider), Some(symbol)) = (row.provider, row.symbol) {
+                aliases.insert(
+                    (provider.to_lowercase(), row.alias.to_uppercase()),
+                    symbol.to_uppercase(),
+                );
+            }
+        }
+
+        let mut shared_aliases = HashMap::new();
+        let mut conflicts = vec![];
+        for ((_, alias), symbol) in &aliases {
+            match shared_aliases.entry(alias.clone()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(symbol.clone());
+                }
+                Entry::Occupied(entry) if entry.get() != symbol => conflicts.push(alias.clone()),
+                Entry::Occupied(_) => {}
+            }
+        }
+        for alias in conflicts {
+            if shared_aliases.remove(&alias).is_some() {
+                warn!(
+                    "Alias {} means different assets on different venues, only used per venue",
+                    alias
+                );
+            }
+        }
+
+        let assets = currencies
+            .into_iter()
+            .filter_map(|row| {
+                let symbol = row.symbol?.to_uppercase();
+                let mut asset_aliases = aliases
+                    .iter()
+                    .filter(|(_, canonical)| **canonical == symbol)
+                    .map(|((source, alias), _)| format!("{}:{}", source, alias))
+                    .collect::<Vec<_>>();
+                asset_aliases.sort();
+
+                Some(Asset {
+                    symbol,
+                    name: row.name,
+                    aliases: asset_aliases,
+                })
+            })
+            .collect();
+
+        Ok(AssetRegistry {
+            assets: Arc::new(assets),
+            aliases: Arc::new(aliases),
+            shared_aliases: Arc::new(shared_aliases),
+        })
+    }
+
+    /// Canonical code of an asset as named by `source`, unknown codes are
+    /// passed through uppercased
+    pub fn canonical(&self, source: &str, code: &str) -> String {
+        let code = code.to_uppercase();
+
+        self.aliases
+            .get(&(source.to_lowercase(), code.clone()))
+            .cloned()
+            .unwrap_or(code)
+    }
+
+    /// Canonical code of an asset named by any venue, for client input.
+    /// Canonical codes are kept, and aliases which mean different assets on
+    /// different venues are left as they are.
+    pub fn resolve(&self, code: &str) -> String {
+        let code = code.to_uppercase();
+        if self.assets.iter().any(|asset| asset.symbol == code) {
+            return code;
+        }
+
+        self.shared_aliases.get(&code).cloned().unwrap_or(code)
+    }
+
+    pub fn all(&self) -> Vec<Asset> {
+        self.assets.to_vec()
+    }
+}
@@ -1,19 +1,92 @@
-use eyre::{bail, Result, WrapErr};
-use futures_util::StreamExt;
-use log::{info, warn};
-use redis::{Client as RedisClient, Commands, ExistenceCheck, SetExpiry, SetOptions, Value};
-use reqwest::Client as ReqwestClient;
+use async_trait::async_trait;
+use eyre::{bail, Result};
+use log::warn;
+use rust_decimal::Decimal;
 use serde::Deserialize;
-use std::fmt;
-use tokio::{time::sleep, time::Duration};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
 
-use crate::{services::ws_message::WsMessage, AppContext};
+use crate::config::Config;
+use crate::services::{
+    connector::{ExchangeConnector, FeedEvent},
+    http,
+    order_book::{BookUpdate, BookUpdateKind},
+    symbols::{SymbolInfo, SymbolRegistry},
+    trade::{Trade, TradeSide},
+    ws_message::WsMessage,
+};
+
+/// Binance allows up to 1024 streams per connection
+pub const STREAMS_PER_CONNECTION: usize = 1024;
+
+/// Keeps SUBSCRIBE requests well below the Binance message size limit
+const STREAMS_PER_REQUEST: usize = 200;
+
+/// Binance allows 5 incoming messages per second, pongs included
+const MAX_MESSAGES_PER_SECOND: u32 = 4;
+
+#[derive(Deserialize, Debug)]
+struct CombinedStreamMessage<T> {
+    data: T,
+}
 
 #[derive(Deserialize, Debug)]
 pub struct BinanceMessage {
-    pub s: String, // Symbol
-    pub c: String, // Price
+    pub s: String,         // Symbol
+    pub c: Decimal,        // Price
+    pub o: Option<String>, // Open price
+    pub h: Option<String>, // High price
+    pub l: Option<String>, // Low price
+    pub v: Option<String>, // Base asset volume
+    pub q: Option<String>, // Quote asset volume
+    pub b: Option<String>, // Best bid price
+    pub a: Option<String>, // Best ask price
+    #[serde(rename = "E")]
+    pub e: Option<i64>, // Event time
+}
+
+#[derive(Deserialize, Debug)]
+struct DepthUpdate {
+    s: String, // Symbol
+    #[serde(rename = "U")]
+    first_update_id: u64,
+    #[serde(rename = "u")]
+    final_update_id: u64,
+    b: Vec<(String, String)>, // Bids
+    a: Vec<(String, String)>, // Asks
+}
+
+/// Payload of both `@trade` and `@aggTrade` streams
+#[derive(Deserialize, Debug)]
+struct TradeMessage {
+    s: String,      // Symbol
+    t: Option<u64>, // Trade ID
+    a: Option<u64>, // Aggregate trade ID
+    p: String,      // Price
+    q: String,      // Quantity
+    #[serde(rename = "T")]
+    trade_time: i64,
+    m: bool, // Is the buyer the market maker?
+}
+
+#[derive(Deserialize, Debug)]
+struct PriceTicker {
+    symbol: String,
+    price: Decimal,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DepthSnapshot {
+    last_update_id: u64,
+    bids: Vec<(String, String)>,
+    asks: Vec<(String, String)>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -22,8 +95,46 @@ struct ExchangeInfo {
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct Symbol {
     symbol: String,
+    status: String,
+    base_asset: String,
+    quote_asset: String,
+    #[serde(default)]
+    filters: Vec<SymbolFilter>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SymbolFilter {
+    filter_type: String,
+    tick_size: Option<String>,
+    step_size: Option<String>,
+    min_notional: Option<String>,
+}
+
+impl From<Symbol> for SymbolInfo {
+    fn from(symbol: Symbol) -> Self {
+        let mut info = SymbolInfo::new(
+            "binance",
+            &symbol.symbol,
+            &symbol.base_asset,
+            &symbol.quote_asset,
+            &symbol.status,
+        );
+
+        for filter in symbol.filters {
+            match filter.filter_type.as_str() {
+                "PRICE_FILTER" => info.tick_size = filter.tick_size,
+                "LOT_SIZE" => info.step_size = filter.step_size,
+                "NOTIONAL" | "MIN_NOTIONAL" => info.min_notional = filter.min_notional,
+                _ => {}
+            }
+        }
+
+        info
+    }
 }
 
 impl fmt::Display for BinanceMessage {
@@ -33,11 +144,10 @@ impl fmt::Display for BinanceMessage {
     }
 }
 
-async fn fetch_market_symbols() -> Result<Vec<String>> {
-    let url = "https://api.binance.com/api/v3/exchangeInfo";
+async fn fetch_market_symbols(api_url: &str) -> Result<Vec<SymbolInfo>> {
+    let url = format!("{}/api/v3/exchangeInfo", api_url);
 
-    let client = ReqwestClient::new();
-    let response = client.get(url).send().await?;
+    let response = http::client().get(url).send().await?;
 
     if response.status().is_success() {
         let body = response.text().await?;
@@ -46,7 +156,7 @@ async fn fetch_market_symbols() -> Result<Vec<String>> {
         let symbols = exchange_info
             .symbols
             .into_iter()
-            .map(|s| s.symbol)
+            .map(|s| s.into())
             .collect();
 
         Ok(symbols)
@@ -57,88 +167,361 @@ async fn fetch_market_symbols() -> Result<Vec<String>> {
     }
 }
 
-pub async fn get_chunked_ws_streams() -> Result<Vec<String>> {
-    let symbols = fetch_market_symbols().await?;
+async fn fetch_depth_snapshot(api_url: &str, symbol: &str) -> Result<DepthSnapshot> {
+    let url = format!("{}/api/v3/depth?symbol={}&limit=1000", api_url, symbol);
+
+    let response = http::client().get(url).send().await?;
+
+    if response.status().is_success() {
+        let body = response.text().await?;
+
+        Ok(serde_json::from_str(&body)?)
+    } else if let Ok(error_body) = response.text().await {
+        bail!(error_body)
+    } else {
+        bail!("Unknown error")
+    }
+}
+
+async fn fetch_prices(api_url: &str) -> Result<Vec<PriceTicker>> {
+    let url = format!("{}/api/v3/ticker/price", api_url);
+
+    let response = http::client().get(url).send().await?;
+
+    if response.status().is_success() {
+        let body = response.text().await?;
+
+        Ok(serde_json::from_str(&body)?)
+    } else if let Ok(error_body) = response.text().await {
+        bail!(error_body)
+    } else {
+        bail!("Unknown error")
+    }
+}
 
-    let chunked_streams = symbols
-        .chunks(300)
+/// Builds SUBSCRIBE/UNSUBSCRIBE requests for the `stream` of every symbol
+fn stream_requests(
+    request_id: &AtomicU64,
+    method: &str,
+    symbols: &[String],
+    stream: &str,
+) -> Result<Vec<Message>> {
+    symbols
+        .chunks(STREAMS_PER_REQUEST)
         .map(|chunk| {
-            chunk
+            let streams = chunk
                 .iter()
-                .map(|symbol| format!("{}@ticker", symbol.to_lowercase()))
-                .collect::<Vec<_>>()
-                .join("/")
+                .map(|symbol| format!("{}@{}", symbol.replace('-', "").to_lowercase(), stream))
+                .collect::<Vec<_>>();
+            let request = json!({
+                "method": method,
+                "params": streams,
+                "id": request_id.fetch_add(1, Ordering::Relaxed),
+            });
+
+            Ok(Message::Text(serde_json::to_string(&request)?))
         })
-        .collect::<Vec<_>>();
-
-    Ok(chunked_streams)
-}
-
-pub async fn subscribe_binance_ticker(app_context: AppContext, streams: &str) -> Result<()> {
-    let url = format!("{}/{}", app_context.config.binance_ws_url, streams);
-
-    let mut redis_connection =
-        RedisClient::open(app_context.config.redis_url.as_str())?.get_connection()?;
-
-    loop {
-        match connect_async(&url).await {
-            Ok((mut ws_stream, _)) => {
-                info!("Connected to Binance WebSocket");
-
-                while let Some(Ok(message)) = ws_stream.next().await {
-                    match message {
-                        Message::Text(data) => {
-                            // info!("Received message: {}", data);
-
-                            let binance_message: BinanceMessage = serde_json::from_str(&data)?;
-                            let ws_message: WsMessage = binance_message.into();
-
-                            let redis_result: Result<Value, redis::RedisError> = redis_connection
-                                .set_options(
-                                    ws_message.get_key(),
-                                    &ws_message.price,
-                                    SetOptions::default()
-                                        .conditional_set(ExistenceCheck::NX)
-                                        .get(true)
-                                        .with_expiration(SetExpiry::EX(20)),
-                                );
-
-                            match redis_result {
-                                Ok(value) => {
-                                    // Only send value, when it's not a cache hit
-                                    if value == Value::Nil {
-                                        let ws_message_string = serde_json::to_string(&ws_message)?;
-
-                                        info!("Sending value to the ws client {}", ws_message);
-                                        app_context
-                                            .ticker_tx
-                                            .send(Message::Text(ws_message_string))
-                                            .map_err(eyre::Report::from)
-                                            .wrap_err("Failed to send WebSocket Binance message")?;
-                                    }
-                                }
-                                Err(err) => {
-                                    warn!("Error setting cache: {}", err);
-                                }
-                            }
-                        }
-                        Message::Close(_) => {
-                            warn!("WebSocket connection closed");
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            Err(err) => {
-                warn!(
-                    "Failed to connect to WebSocket: {}. Retrying in 5 seconds...",
-                    err
-                );
+        .collect()
+}
+
+/// Stream symbols of `BASE-QUOTE` symbols, `BTCUSDT` -> `BTC-USDT`
+fn venue_symbols(symbols: Vec<String>) -> HashMap<String, String> {
+    symbols
+        .into_iter()
+        .map(|symbol| (symbol.replace('-', ""), symbol))
+        .collect()
+}
+
+/// Responses to SUBSCRIBE/UNSUBSCRIBE requests carry the request id
+fn is_request_response(v: &serde_json::Value) -> bool {
+    if v.get("id").is_none() {
+        return false;
+    }
+
+    if let Some(error) = v.get("error") {
+        warn!("Binance request {} failed: {}", v["id"], error);
+    }
+
+    true
+}
+
+pub struct BinanceConnector {
+    ws_url: String,
+    api_url: String,
+    request_id: AtomicU64,
+    symbols: SymbolRegistry,
+}
+
+impl BinanceConnector {
+    pub fn new(config: &Config, symbols: &SymbolRegistry) -> Self {
+        BinanceConnector {
+            ws_url: config.binance_ws_url.clone(),
+            api_url: config.binance_api_url.clone(),
+            request_id: AtomicU64::new(1),
+            symbols: symbols.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BinanceConnector {
+    fn name(&self) -> &'static str {
+        "Binance"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        stream_requests(&self.request_id, "SUBSCRIBE", symbols, "ticker")
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        stream_requests(&self.request_id, "UNSUBSCRIBE", symbols, "ticker")
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        if is_request_response(&v) {
+            return Ok(vec![]);
+        }
+
+        let message: CombinedStreamMessage<BinanceMessage> = serde_json::from_value(v)?;
+
+        match self.symbols.get("binance", &message.data.s) {
+            Some(info) => Ok(vec![FeedEvent::Ticker(WsMessage::from_binance(
+                &info,
+                message.data,
+            ))]),
+            None => {
+                warn!("Unknown Binance symbol: {}", message.data.s);
+                Ok(vec![])
             }
         }
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        let infos = fetch_market_symbols(&self.api_url).await?;
+
+        let symbols = infos
+            .iter()
+            .filter(|info| info.status == "TRADING" && self.symbols.allows(info))
+            .map(|info| info.venue_symbol.clone())
+            .collect();
+        self.symbols.replace("binance", infos);
+
+        Ok(symbols)
+    }
+
+    fn max_messages_per_second(&self) -> Option<u32> {
+        Some(MAX_MESSAGES_PER_SECOND)
+    }
+
+    // Prices of all symbols cost 4 of the 6000 request weight per minute
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(5))
+    }
+
+    async fn poll_tickers(&self, symbols: &[String]) -> Result<Vec<WsMessage>> {
+        let symbols = symbols.iter().collect::<HashSet<_>>();
+
+        let tickers = fetch_prices(&self.api_url)
+            .await?
+            .into_iter()
+            .filter(|ticker| symbols.contains(&ticker.symbol))
+            .filter_map(|ticker| {
+                let info = self.symbols.get("binance", &ticker.symbol)?;
+
+                Some(WsMessage::new(
+                    &info.source,
+                    &info.base,
+                    &info.quote,
+                    ticker.price,
+                ))
+            })
+            .collect();
+
+        Ok(tickers)
+    }
+}
+
+/// Diff depth streams of the symbols (in `BASE-QUOTE` form) listed in
+/// `ORDER_BOOK_SYMBOLS`, synced with REST snapshots
+pub struct BinanceBookConnector {
+    ws_url: String,
+    api_url: String,
+    request_id: AtomicU64,
+    symbols: HashMap<String, String>,
+}
+
+impl BinanceBookConnector {
+    pub fn new(config: &Config) -> Self {
+        BinanceBookConnector {
+            ws_url: config.binance_ws_url.clone(),
+            api_url: config.binance_api_url.clone(),
+            request_id: AtomicU64::new(1),
+            symbols: venue_symbols(config.order_book_symbols()),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BinanceBookConnector {
+    fn name(&self) -> &'static str {
+        "Binance depth"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        stream_requests(&self.request_id, "SUBSCRIBE", symbols, "depth@100ms")
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        stream_requests(&self.request_id, "UNSUBSCRIBE", symbols, "depth@100ms")
+    }
+
+    fn serves_tickers(&self) -> bool {
+        false
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        if is_request_response(&v) {
+            return Ok(vec![]);
+        }
+
+        let message: CombinedStreamMessage<DepthUpdate> = serde_json::from_value(v)?;
+        let depth = message.data;
+
+        let symbol = match self.symbols.get(&depth.s) {
+            Some(symbol) => symbol.clone(),
+            None => return Ok(vec![]),
+        };
+
+        Ok(vec![FeedEvent::Book(BookUpdate {
+            source: "binance".to_string(),
+            symbol,
+            kind: BookUpdateKind::Diff {
+                first: Some(depth.first_update_id),
+                last: Some(depth.final_update_id),
+            },
+            bids: depth.b,
+            asks: depth.a,
+        })])
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        Ok(self.symbols.values().cloned().collect())
+    }
+
+    async fn fetch_book_snapshot(&self, symbol: &str) -> Result<BookUpdate> {
+        let snapshot = fetch_depth_snapshot(&self.api_url, &symbol.replace('-', "")).await?;
+
+        Ok(BookUpdate {
+            source: "binance".to_string(),
+            symbol: symbol.to_string(),
+            kind: BookUpdateKind::Snapshot {
+                sequence: Some(snapshot.last_update_id),
+            },
+            bids: snapshot.bids,
+            asks: snapshot.asks,
+        })
+    }
+
+    fn max_messages_per_second(&self) -> Option<u32> {
+        Some(MAX_MESSAGES_PER_SECOND)
+    }
+}
+
+/// `@trade` or `@aggTrade` streams (see `BINANCE_TRADE_STREAM`) of the symbols
+/// listed in `TRADE_SYMBOLS`
+pub struct BinanceTradeConnector {
+    ws_url: String,
+    stream: String,
+    request_id: AtomicU64,
+    symbols: HashMap<String, String>,
+}
+
+impl BinanceTradeConnector {
+    pub fn new(config: &Config) -> Self {
+        BinanceTradeConnector {
+            ws_url: config.binance_ws_url.clone(),
+            stream: config.binance_trade_stream.clone(),
+            request_id: AtomicU64::new(1),
+            symbols: venue_symbols(config.trade_symbols()),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BinanceTradeConnector {
+    fn name(&self) -> &'static str {
+        "Binance trades"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        stream_requests(&self.request_id, "SUBSCRIBE", symbols, &self.stream)
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        stream_requests(&self.request_id, "UNSUBSCRIBE", symbols, &self.stream)
+    }
+
+    fn serves_tickers(&self) -> bool {
+        false
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        if is_request_response(&v) {
+            return Ok(vec![]);
+        }
+
+        let message: CombinedStreamMessage<TradeMessage> = serde_json::from_value(v)?;
+        let trade_message = message.data;
+
+        let symbol = match self.symbols.get(&trade_message.s) {
+            Some(symbol) => symbol,
+            None => return Ok(vec![]),
+        };
+        let trade_id = trade_message.t.or(trade_message.a).unwrap_or_default();
+
+        // Buyer being the maker means the taker sold
+        let side = if trade_message.m {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+
+        let mut trade = Trade::new(
+            "binance",
+            symbol,
+            trade_id.to_string(),
+            trade_message.p,
+            trade_message.q,
+            side,
+        );
+        trade.exchange_time = Some(trade_message.trade_time);
+
+        Ok(vec![FeedEvent::Trade(trade)])
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        Ok(self.symbols.values().cloned().collect())
+    }
 
-        // Wait 5 seconds trying to reconnect
-        sleep(Duration::from_secs(5)).await;
+    fn max_messages_per_second(&self) -> Option<u32> {
+        Some(MAX_MESSAGES_PER_SECOND)
     }
 }
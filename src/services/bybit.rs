@@ -0,0 +1,213 @@
+use async_trait::async_trait;
+use eyre::{bail, Result};
+use log::warn;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt;
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::config::Config;
+use crate::services::{
+    connector::{ExchangeConnector, FeedEvent},
+    http,
+    symbols::{SymbolInfo, SymbolRegistry},
+    ws_message::WsMessage,
+};
+
+/// Bybit rejects subscribe requests with more than 10 topics on spot
+const TOPICS_PER_REQUEST: usize = 10;
+
+#[derive(Deserialize, Debug)]
+pub struct BybitMessage {
+    pub data: BybitTicker,
+    pub ts: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitTicker {
+    pub symbol: String,
+    pub last_price: Decimal,
+    pub prev_price_24h: Option<String>,
+    pub high_price_24h: Option<String>,
+    pub low_price_24h: Option<String>,
+    pub volume_24h: Option<String>,
+    pub turnover_24h: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct InstrumentsResponse {
+    ret_code: i64,
+    ret_msg: String,
+    result: Option<InstrumentsResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InstrumentsResult {
+    list: Vec<Instrument>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Instrument {
+    symbol: String,
+    base_coin: String,
+    quote_coin: String,
+    status: String,
+    price_filter: Option<PriceFilter>,
+    lot_size_filter: Option<LotSizeFilter>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PriceFilter {
+    tick_size: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LotSizeFilter {
+    base_precision: Option<String>,
+    min_order_amt: Option<String>,
+}
+
+impl From<Instrument> for SymbolInfo {
+    fn from(instrument: Instrument) -> Self {
+        let mut info = SymbolInfo::new(
+            "bybit",
+            &instrument.symbol,
+            &instrument.base_coin,
+            &instrument.quote_coin,
+            &instrument.status,
+        );
+        info.tick_size = instrument.price_filter.and_then(|filter| filter.tick_size);
+        if let Some(filter) = instrument.lot_size_filter {
+            info.step_size = filter.base_precision;
+            info.min_notional = filter.min_order_amt;
+        }
+
+        info
+    }
+}
+
+impl fmt::Display for BybitTicker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(Ticker: {} - {})", self.symbol, self.last_price)
+    }
+}
+
+async fn fetch_instruments(api_url: &str) -> Result<Vec<SymbolInfo>> {
+    let url = format!("{}/v5/market/instruments-info?category=spot", api_url);
+
+    let response = http::client().get(url).send().await?;
+
+    if response.status().is_success() {
+        let body = response.text().await?;
+        let instruments: InstrumentsResponse = serde_json::from_str(&body)?;
+
+        match instruments.result {
+            Some(result) if instruments.ret_code == 0 => Ok(result
+                .list
+                .into_iter()
+                .map(|instrument| instrument.into())
+                .collect()),
+            _ => bail!(instruments.ret_msg),
+        }
+    } else if let Ok(error_body) = response.text().await {
+        bail!(error_body)
+    } else {
+        bail!("Unknown error")
+    }
+}
+
+pub struct BybitConnector {
+    ws_url: String,
+    api_url: String,
+    symbols: SymbolRegistry,
+}
+
+impl BybitConnector {
+    pub fn new(config: &Config, symbols: &SymbolRegistry) -> Self {
+        BybitConnector {
+            ws_url: config.bybit_ws_url.clone(),
+            api_url: config.bybit_api_url.clone(),
+            symbols: symbols.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for BybitConnector {
+    fn name(&self) -> &'static str {
+        "Bybit"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        symbols
+            .chunks(TOPICS_PER_REQUEST)
+            .map(|chunk| {
+                let args = chunk
+                    .iter()
+                    .map(|symbol| format!("tickers.{}", symbol))
+                    .collect::<Vec<_>>();
+                let subscribe_msg = json!({ "op": "subscribe", "args": args });
+
+                Ok(Message::Text(serde_json::to_string(&subscribe_msg)?))
+            })
+            .collect()
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        // Skip pongs and subscription acks
+        let is_ticker = v["topic"]
+            .as_str()
+            .map_or(false, |topic| topic.starts_with("tickers."));
+        if !is_ticker {
+            return Ok(vec![]);
+        }
+
+        let bybit_message: BybitMessage = serde_json::from_value(v)?;
+
+        match self.symbols.get("bybit", &bybit_message.data.symbol) {
+            Some(info) => Ok(vec![FeedEvent::Ticker(WsMessage::from_bybit(
+                &info,
+                bybit_message,
+            ))]),
+            None => {
+                warn!("Unknown Bybit symbol: {}", bybit_message.data.symbol);
+                Ok(vec![])
+            }
+        }
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        let infos = fetch_instruments(&self.api_url).await?;
+
+        let symbols = infos
+            .iter()
+            .filter(|info| info.status == "Trading" && self.symbols.allows(info))
+            .map(|info| info.venue_symbol.clone())
+            .collect();
+        self.symbols.replace("bybit", infos);
+
+        Ok(symbols)
+    }
+
+    // Bybit drops connections without a ping for more than 20 seconds
+    fn ping_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(15))
+    }
+
+    fn ping_message(&self) -> Message {
+        Message::Text(json!({ "op": "ping" }).to_string())
+    }
+}
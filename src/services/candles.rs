@@ -0,0 +1,530 @@
+use async_graphql::SimpleObject;
+use chrono::Utc;
+use eyre::Result;
+use log::{info, warn};
+use rust_decimal::prelude::ToPrimitive;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    time::{interval, Duration},
+};
+
+use crate::services::{connector::broadcast, trade::Trade, ws_message::WsMessage};
+use crate::AppContext;
+
+/// Candle intervals as (name, length in milliseconds)
+pub const INTERVALS: [(&str, i64); 4] = [
+    ("1m", 60_000),
+    ("5m", 300_000),
+    ("1h", 3_600_000),
+    ("1d", 86_400_000),
+];
+
+/// Ticks and trades waiting for the engine, newer ones are dropped while it
+/// is full
+const CANDLE_BUFFER: usize = 10_000;
+
+/// Gap candles written in a row for a symbol without ticks, e.g. a delisted
+/// one, before its live candle is dropped
+const MAX_GAP_CANDLES: u32 = 60;
+
+#[derive(Serialize, Clone, Debug, SimpleObject)]
+pub struct Candle {
+    #[serde(rename = "type")]
+    #[graphql(skip)]
+    pub message_type: &'static str,
+    pub source: String,
+    pub symbol: String,
+    pub interval: String,
+    /// Start of the candle in milliseconds since the epoch
+    pub open_time: i64,
+    /// OHLC are empty for intervals without ticks when gaps are not carried forward
+    pub open: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    pub close: Option<f64>,
+    /// Traded volume, only known for candles built from trades
+    pub volume: f64,
+    /// Number of ticks, or trades for symbols with a trade feed
+    pub tick_count: i64,
+    pub closed: bool,
+    /// Intervals in a row without ticks, up to this one
+    #[serde(skip)]
+    #[graphql(skip)]
+    gaps: u32,
+}
+
+impl Candle {
+    fn new(source: &str, symbol: &str, interval: &str, open_time: i64) -> Self {
+        Candle {
+            message_type: "candle",
+            source: source.to_string(),
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            open_time,
+            open: None,
+            high: None,
+            low: None,
+            close: None,
+            volume: 0.0,
+            tick_count: 0,
+            closed: false,
+            gaps: 0,
+        }
+    }
+
+    fn update(&mut self, price: f64, size: Option<f64>) {
+        self.open = self.open.or(Some(price));
+        self.high = Some(self.high.map_or(price, |high| high.max(price)));
+        self.low = Some(self.low.map_or(price, |low| low.min(price)));
+        self.close = Some(price);
+        self.volume += size.unwrap_or(0.0);
+        self.tick_count += 1;
+        self.gaps = 0;
+    }
+
+    /// Next candle of an interval without ticks
+    fn gap(&self, interval_ms: i64, carry_forward: bool) -> Self {
+        let mut candle = Candle::new(
+            &self.source,
+            &self.symbol,
+            &self.interval,
+            self.open_time + interval_ms,
+        );
+        candle.gaps = self.gaps + 1;
+
+        if carry_forward {
+            candle.open = self.close;
+            candle.high = self.close;
+            candle.low = self.close;
+            candle.close = self.close;
+        }
+
+        candle
+    }
+
+    /// Closes the candle and every gap candle after it which ended before
+    /// `time`. Past `MAX_GAP_CANDLES` the candle skips ahead to `time` without
+    /// gap candles and is left for `close_candles` to drop.
+    fn roll(&mut self, time: i64, interval_ms: i64, carry_forward: bool, closed: &mut Vec<Candle>) {
+        while self.open_time + interval_ms <= time {
+            let next = if self.gaps < MAX_GAP_CANDLES {
+                self.gap(interval_ms, carry_forward)
+            } else {
+                let open_time = time - time % interval_ms;
+                let mut idle = Candle::new(&self.source, &self.symbol, &self.interval, open_time);
+                idle.gaps = self.gaps + 1;
+                idle
+            };
+            let mut done = std::mem::replace(self, next);
+            done.closed = true;
+            closed.push(done);
+        }
+    }
+
+    fn key(&self) -> String {
+        candle_key(&self.source, &self.symbol, &self.interval)
+    }
+}
+
+fn candle_key(source: &str, symbol: &str, interval: &str) -> String {
+    format!("{}-{}-{}", source, symbol, interval)
+}
+
+#[derive(Debug)]
+struct CandleInput {
+    source: String,
+    symbol: String,
+    price: f64,
+    size: Option<f64>,
+    time: i64,
+}
+
+/// Live candles, fed by the connectors and closed by `run_candle_engine`.
+/// Symbols with a trade feed get their candles from trades, the others from
+/// ticker prices.
+#[derive(Clone)]
+pub struct CandleStore {
+    live: Arc<RwLock<HashMap<String, Candle>>>,
+    // `source-symbol` of the symbols with a trade feed
+    trade_fed: Arc<RwLock<HashSet<String>>>,
+    input_tx: mpsc::Sender<CandleInput>,
+    // Inputs dropped since the engine last closed candles
+    dropped: Arc<AtomicU64>,
+}
+
+pub struct CandleReceiver(mpsc::Receiver<CandleInput>);
+
+impl CandleStore {
+    pub fn new() -> (Self, CandleReceiver) {
+        let (input_tx, input_rx) = mpsc::channel(CANDLE_BUFFER);
+
+        let store = CandleStore {
+            live: Arc::new(RwLock::new(HashMap::new())),
+            trade_fed: Arc::new(RwLock::new(HashSet::new())),
+            input_tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+
+        (store, CandleReceiver(input_rx))
+    }
+
+    /// Builds the candles of the `BASE-QUOTE` symbols from trades only
+    pub fn use_trades(&self, source: &str, symbols: &[String]) {
+        self.trade_fed.write().unwrap().extend(
+            symbols
+                .iter()
+                .map(|symbol| format!("{}-{}", source, symbol)),
+        );
+    }
+
+    pub fn record_tick(&self, ws_message: &WsMessage) {
+        if self
+            .trade_fed
+            .read()
+            .unwrap()
+            .contains(&ws_message.get_key())
+        {
+            return;
+        }
+
+        if let Some(price) = ws_message.price.to_f64() {
+            self.record(
+                ws_message.get_source(),
+                &ws_message.get_symbol(),
+                price,
+                None,
+            );
+        }
+    }
+
+    pub fn record_trade(&self, trade: &Trade) {
+        let symbol = format!("{}-{}", trade.base, trade.quote);
+
+        if let Ok(price) = trade.price.parse() {
+            self.record(&trade.source, &symbol, price, trade.size.parse().ok());
+        }
+    }
+
+    fn record(&self, source: &str, symbol: &str, price: f64, size: Option<f64>) {
+        let input = CandleInput {
+            source: source.to_string(),
+            symbol: symbol.to_string(),
+            price,
+            size,
+            time: Utc::now().timestamp_millis(),
+        };
+
+        // A closed channel means the engine is gone, which is logged there
+        if let Err(TrySendError::Full(_)) = self.input_tx.try_send(input) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn live(&self, source: &str, symbol: &str, interval: &str) -> Option<Candle> {
+        let key = candle_key(source, symbol, interval);
+
+        self.live.read().unwrap().get(&key).cloned()
+    }
+}
+
+/// Builds candles of every interval from the recorded ticks, closes them when
+/// their interval ends, persists closed candles and sends candle updates to
+/// the websocket clients once a second
+pub async fn run_candle_engine(app_context: AppContext, receiver: CandleReceiver) {
+    let CandleReceiver(mut input_rx) = receiver;
+    let live = app_context.candles.live.clone();
+    let dropped = app_context.candles.dropped.clone();
+    let carry_forward = app_context.config.candle_gap_fill == "carry";
+
+    let mut timer = interval(Duration::from_secs(1));
+    let mut changed = HashSet::new();
+    let mut closed = vec![];
+
+    loop {
+        tokio::select! {
+            input = input_rx.recv() => {
+                let input = match input {
+                    Some(input) => input,
+                    None => break,
+                };
+
+                let mut live = live.write().unwrap();
+                for (interval, interval_ms) in INTERVALS {
+                    let open_time = input.time - input.time % interval_ms;
+                    let key = candle_key(&input.source, &input.symbol, interval);
+
+                    let candle = live.entry(key.clone()).or_insert_with(|| {
+                        Candle::new(&input.source, &input.symbol, interval, open_time)
+                    });
+                    candle.roll(input.time, interval_ms, carry_forward, &mut closed);
+                    candle.update(input.price, input.size);
+                    changed.insert(key);
+                }
+            }
+            _ = timer.tick() => {
+                let dropped = dropped.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    warn!("Dropped {} candle ticks, the candle engine is behind", dropped);
+                }
+
+                let now = Utc::now().timestamp_millis();
+                close_candles(&live, now, carry_forward, &mut closed);
+
+                for candle in &closed {
+                    changed.remove(&candle.key());
+                    send_candle(&app_context, candle);
+                }
+
+                let updated = {
+                    let live = live.read().unwrap();
+                    changed
+                        .drain()
+                        .filter_map(|key| live.get(&key).cloned())
+                        .collect::<Vec<_>>()
+                };
+                for candle in &updated {
+                    send_candle(&app_context, candle);
+                }
+
+                if !closed.is_empty() {
+                    if let Err(err) = save_candles(&app_context.db_connection, &closed).await {
+                        warn!("Failed to save candles: {}", err);
+                    }
+                    closed.clear();
+                }
+            }
+        }
+    }
+
+    warn!("Candle engine stopped, no more ticks");
+}
+
+/// Closes every live candle whose interval has ended, replacing it with the
+/// next one. Intervals without ticks produce gap candles, up to
+/// `MAX_GAP_CANDLES` after which the candle is dropped until the symbol ticks
+/// again.
+fn close_candles(
+    live: &RwLock<HashMap<String, Candle>>,
+    now: i64,
+    carry_forward: bool,
+    closed: &mut Vec<Candle>,
+) {
+    let mut live = live.write().unwrap();
+
+    for candle in live.values_mut() {
+        if let Some((_, interval_ms)) = INTERVALS.iter().find(|(name, _)| *name == candle.interval)
+        {
+            candle.roll(now, *interval_ms, carry_forward, closed);
+        }
+    }
+
+    live.retain(|_, candle| candle.gaps <= MAX_GAP_CANDLES);
+}
+
+fn send_candle(app_context: &AppContext, candle: &Candle) {
+    if let Err(err) = broadcast(&app_context.candle_tx, candle) {
+        warn!("Failed to send candle: {}", err);
+    }
+}
+
+async fn save_candles(db_connection: &SqlitePool, candles: &[Candle]) -> Result<()> {
+    let mut transaction = db_connection.begin().await?;
+
+    for candle in candles {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO candles
+               (source, symbol, interval, open_time, open, high, low, close, volume, tick_count)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            candle.source,
+            candle.symbol,
+            candle.interval,
+            candle.open_time,
+            candle.open,
+            candle.high,
+            candle.low,
+            candle.close,
+            candle.volume,
+            candle.tick_count,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    info!("Saved {} closed candles", candles.len());
+
+    Ok(())
+}
+
+/// Closed candles, newest first
+pub async fn fetch_candles(
+    db_connection: &SqlitePool,
+    source: &str,
+    symbol: &str,
+    interval: &str,
+    limit: i64,
+) -> Result<Vec<Candle>> {
+    let rows = sqlx::query!(
+        "SELECT source, symbol, interval, open_time, open, high, low, close, volume, tick_count
+         FROM candles
+         WHERE source = ? AND symbol = ? AND interval = ?
+         ORDER BY open_time DESC
+         LIMIT ?",
+        source,
+        symbol,
+        interval,
+        limit,
+    )
+    .fetch_all(db_connection)
+    .await?;
+
+    let candles = rows
+        .into_iter()
+        .map(|row| Candle {
+            message_type: "candle",
+            source: row.source,
+            symbol: row.symbol,
+            interval: row.interval,
+            open_time: row.open_time,
+            open: row.open,
+            high: row.high,
+            low: row.low,
+            close: row.close,
+            volume: row.volume,
+            tick_count: row.tick_count,
+            closed: true,
+            gaps: 0,
+        })
+        .collect();
+
+    Ok(candles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60_000;
+
+    fn candle(prices: &[f64]) -> Candle {
+        let mut candle = Candle::new("binance", "BTC-USDT", "1m", 0);
+        for price in prices {
+            candle.update(*price, Some(0.5));
+        }
+        candle
+    }
+
+    fn ohlc(candle: &Candle) -> [Option<f64>; 4] {
+        [candle.open, candle.high, candle.low, candle.close]
+    }
+
+    #[test]
+    fn update_tracks_ohlc() {
+        let candle = candle(&[10.0, 12.0, 9.0, 11.0]);
+
+        assert_eq!(
+            ohlc(&candle),
+            [Some(10.0), Some(12.0), Some(9.0), Some(11.0)]
+        );
+        assert_eq!(candle.volume, 2.0);
+        assert_eq!(candle.tick_count, 4);
+    }
+
+    #[test]
+    fn roll_keeps_the_candle_within_its_interval() {
+        let mut candle = candle(&[10.0]);
+        let mut closed = vec![];
+
+        candle.roll(MINUTE - 1, MINUTE, true, &mut closed);
+
+        assert!(closed.is_empty());
+        assert_eq!(candle.open_time, 0);
+    }
+
+    #[test]
+    fn roll_closes_the_candle_and_starts_the_next() {
+        let mut candle = candle(&[10.0, 11.0]);
+        let mut closed = vec![];
+
+        candle.roll(MINUTE + 5, MINUTE, false, &mut closed);
+        candle.update(12.0, None);
+
+        assert_eq!(closed.len(), 1);
+        assert!(closed[0].closed);
+        assert_eq!(closed[0].open_time, 0);
+        assert_eq!(
+            ohlc(&closed[0]),
+            [Some(10.0), Some(11.0), Some(10.0), Some(11.0)]
+        );
+
+        assert!(!candle.closed);
+        assert_eq!(candle.open_time, MINUTE);
+        assert_eq!(ohlc(&candle), [Some(12.0); 4]);
+        assert_eq!(candle.tick_count, 1);
+    }
+
+    #[test]
+    fn gaps_carry_the_close_forward() {
+        let mut candle = candle(&[10.0, 11.0]);
+        let mut closed = vec![];
+
+        candle.roll(3 * MINUTE, MINUTE, true, &mut closed);
+
+        let open_times = closed.iter().map(|c| c.open_time).collect::<Vec<_>>();
+        assert_eq!(open_times, vec![0, MINUTE, 2 * MINUTE]);
+        for gap in &closed[1..] {
+            assert_eq!(ohlc(gap), [Some(11.0); 4]);
+            assert_eq!(gap.tick_count, 0);
+            assert_eq!(gap.volume, 0.0);
+        }
+        assert_eq!(ohlc(&candle), [Some(11.0); 4]);
+    }
+
+    #[test]
+    fn gaps_stay_empty_without_carry_forward() {
+        let mut candle = candle(&[10.0]);
+        let mut closed = vec![];
+
+        candle.roll(2 * MINUTE, MINUTE, false, &mut closed);
+
+        assert_eq!(closed.len(), 2);
+        assert_eq!(ohlc(&closed[1]), [None; 4]);
+        assert_eq!(ohlc(&candle), [None; 4]);
+    }
+
+    #[test]
+    fn close_candles_drops_idle_symbols() {
+        let live = RwLock::new(HashMap::new());
+        let idle = candle(&[10.0]);
+        let mut active = candle(&[20.0]);
+        active.symbol = "ETH-USDT".to_string();
+        live.write().unwrap().insert(idle.key(), idle);
+        live.write().unwrap().insert(active.key(), active);
+        let mut closed = vec![];
+
+        // Gap candles stop at the limit even when the engine was held up
+        let now = (MAX_GAP_CANDLES as i64 + 10) * MINUTE;
+        close_candles(&live, now - MINUTE, true, &mut closed);
+        assert_eq!(closed.len(), 2 * (MAX_GAP_CANDLES as usize + 1));
+        assert!(live.read().unwrap().is_empty());
+
+        // A tick brings the symbol back in the current interval
+        let mut candle = Candle::new("binance", "BTC-USDT", "1m", now);
+        candle.update(10.0, None);
+        live.write().unwrap().insert(candle.key(), candle);
+        closed.clear();
+
+        close_candles(&live, now + 1, true, &mut closed);
+        assert!(closed.is_empty());
+        assert_eq!(live.read().unwrap().len(), 1);
+    }
+}
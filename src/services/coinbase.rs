@@ -1,31 +1,117 @@
+use async_trait::async_trait;
+use chrono::DateTime;
 use eyre::{bail, Result};
-use futures_util::{SinkExt, StreamExt};
-use log::{info, warn};
-use redis::{Client as RedisClient, Commands, ExistenceCheck, SetExpiry, SetOptions, Value};
-use reqwest::Client as ReqwestClient;
+use log::warn;
+use rust_decimal::Decimal;
 use serde::Deserialize;
-use std::fmt;
-use tokio::{time::sleep, time::Duration};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use serde_json::json;
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::time::{sleep, timeout_at, Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
 
-use crate::{services::ws_message::WsMessage, AppContext};
+use crate::config::Config;
+use crate::services::{
+    connector::{ExchangeConnector, FeedEvent},
+    http,
+    order_book::{BookUpdate, BookUpdateKind},
+    symbols::{SymbolInfo, SymbolRegistry},
+    trade::{Trade, TradeSide},
+    ws_message::WsMessage,
+};
 
-#[derive(Deserialize)]
-struct CoinbaseResponse {
-    data: TickerData,
+/// Keeps subscribe messages well below the Coinbase message size limit
+const PRODUCTS_PER_REQUEST: usize = 100;
+
+/// Coinbase allows 10 public REST requests per second
+const POLL_REQUEST_GAP: Duration = Duration::from_millis(150);
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug)]
+struct Product {
+    id: String,
+    base_currency: String,
+    quote_currency: String,
+    status: String,
+    #[serde(default)]
+    trading_disabled: bool,
+    quote_increment: Option<String>,
+    base_increment: Option<String>,
+    min_market_funds: Option<String>,
+}
+
+impl From<Product> for SymbolInfo {
+    fn from(product: Product) -> Self {
+        // Products with trading disabled still report being online
+        let status = if product.trading_disabled {
+            "trading_disabled"
+        } else {
+            &product.status
+        };
+
+        let mut info = SymbolInfo::new(
+            "coinbase",
+            &product.id,
+            &product.base_currency,
+            &product.quote_currency,
+            status,
+        );
+        info.tick_size = product.quote_increment;
+        info.step_size = product.base_increment;
+        info.min_notional = product.min_market_funds;
+
+        info
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ProductTicker {
+    price: Decimal,
+    bid: Option<String>,
+    ask: Option<String>,
+    volume: Option<String>,
+    time: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Level2Snapshot {
+    product_id: String,
+    bids: Vec<(String, String)>,
+    asks: Vec<(String, String)>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Level2Update {
+    product_id: String,
+    // (side, price, size)
+    changes: Vec<(String, String, String)>,
 }
 
 #[derive(Deserialize, Debug)]
-pub struct TickerData {
-    pub amount: String,
-    pub base: String,
-    pub currency: String,
+struct MatchMessage {
+    trade_id: u64,
+    product_id: String,
+    price: String,
+    size: String,
+    // Side of the maker order
+    side: String,
+    time: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct CoinbaseMessage {
     pub product_id: String,
-    pub price: String,
+    pub price: Decimal,
+    pub open_24h: Option<String>,
+    pub high_24h: Option<String>,
+    pub low_24h: Option<String>,
+    pub volume_24h: Option<String>,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    pub time: Option<String>,
 }
 
 impl fmt::Display for CoinbaseMessage {
@@ -35,17 +121,41 @@ impl fmt::Display for CoinbaseMessage {
     }
 }
 
-pub async fn fetch_coinbase_price(base: &str, quote: &str) -> Result<TickerData> {
-    let url = format!("https://api.coinbase.com/v2/prices/{}-{}/buy", base, quote);
+async fn fetch_products(api_url: &str) -> Result<Vec<SymbolInfo>> {
+    let url = format!("{}/products", api_url);
+
+    // Coinbase Exchange API rejects requests without a user agent
+    let response = http::client()
+        .get(url)
+        .header("User-Agent", "rust-ticker-server")
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        let body = response.text().await?;
+        let products: Vec<Product> = serde_json::from_str(&body)?;
+
+        Ok(products.into_iter().map(|product| product.into()).collect())
+    } else if let Ok(error_body) = response.text().await {
+        bail!(error_body)
+    } else {
+        bail!("Unknown error")
+    }
+}
+
+async fn fetch_product_ticker(api_url: &str, product_id: &str) -> Result<ProductTicker> {
+    let url = format!("{}/products/{}/ticker", api_url, product_id);
 
-    let client = ReqwestClient::new();
-    let response = client.get(url).send().await?;
+    let response = http::client()
+        .get(url)
+        .header("User-Agent", "rust-ticker-server")
+        .send()
+        .await?;
 
     if response.status().is_success() {
         let body = response.text().await?;
-        let ticker_data: CoinbaseResponse = serde_json::from_str(&body)?;
 
-        Ok(ticker_data.data)
+        Ok(serde_json::from_str(&body)?)
     } else if let Ok(error_body) = response.text().await {
         bail!(error_body)
     } else {
@@ -53,83 +163,312 @@ pub async fn fetch_coinbase_price(base: &str, quote: &str) -> Result<TickerData>
     }
 }
 
-pub async fn subscribe_coinbase_ticker(app_context: AppContext) -> Result<()> {
-    let mut redis_connection = RedisClient::open(app_context.config.redis_url)?.get_connection()?;
-
-    loop {
-        match connect_async(app_context.config.coinbase_ws_url.as_str()).await {
-            Ok((mut ws_stream, _)) => {
-                info!("Connected to Coinbase WebSocket");
-
-                let subscribe_msg = r#"{
-                  "type": "subscribe",
-                  "channels": [{ "name": "ticker", "product_ids": ["BTC-USDT"] }, { "name": "ticker", "product_ids": ["ETH-USDT"] }]
-                }"#;
-
-                ws_stream.send(Message::Text(subscribe_msg.into())).await?;
-                info!("Subscribed to ticker channel");
-
-                while let Some(Ok(message)) = ws_stream.next().await {
-                    match message {
-                        Message::Text(data) => {
-                            // info!("Received message: {}", data);
-
-                            let v = serde_json::from_str::<serde_json::Value>(&data)?;
-
-                            // We only care about ticker messages
-                            if v["type"] == "ticker" {
-                                let coinbase_message: CoinbaseMessage =
-                                    serde_json::from_str(&data)?;
-                                let ws_message: WsMessage = coinbase_message.into();
-
-                                // info!("{}", coinbase_message);
-
-                                let redis_result: Result<Value, redis::RedisError> =
-                                    redis_connection.set_options(
-                                        ws_message.get_key(),
-                                        &ws_message.price,
-                                        SetOptions::default()
-                                            .conditional_set(ExistenceCheck::NX)
-                                            .get(true)
-                                            .with_expiration(SetExpiry::EX(20)),
-                                    );
-
-                                match redis_result {
-                                    Ok(value) => {
-                                        // Only send value, when it's not a cache hit
-                                        if value == Value::Nil {
-                                            let ws_message_string =
-                                                serde_json::to_string(&ws_message)?;
-
-                                            info!("Sending value to the ws client {}", ws_message);
-                                            app_context
-                                                .ticker_tx
-                                                .send(Message::Text(ws_message_string))?;
-                                        }
-                                    }
-                                    Err(err) => {
-                                        warn!("Error setting cache: {}", err);
-                                    }
-                                }
-                            }
-                        }
-                        Message::Close(_) => {
-                            info!("WebSocket connection closed");
-                            break;
-                        }
-                        _ => {}
-                    }
+fn channel_messages(message_type: &str, channel: &str, symbols: &[String]) -> Result<Vec<Message>> {
+    symbols
+        .chunks(PRODUCTS_PER_REQUEST)
+        .map(|chunk| {
+            let msg = json!({
+                "type": message_type,
+                "channels": [{ "name": channel, "product_ids": chunk }]
+            });
+
+            Ok(Message::Text(serde_json::to_string(&msg)?))
+        })
+        .collect()
+}
+
+pub struct CoinbaseConnector {
+    ws_url: String,
+    api_url: String,
+    symbols: SymbolRegistry,
+    // Index of the product the next poll cycle starts with
+    poll_cursor: AtomicUsize,
+}
+
+impl CoinbaseConnector {
+    pub fn new(config: &Config, symbols: &SymbolRegistry) -> Self {
+        CoinbaseConnector {
+            ws_url: config.coinbase_ws_url.clone(),
+            api_url: config.coinbase_api_url.clone(),
+            symbols: symbols.clone(),
+            poll_cursor: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for CoinbaseConnector {
+    fn name(&self) -> &'static str {
+        "Coinbase"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        channel_messages("subscribe", "ticker", symbols)
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        channel_messages("unsubscribe", "ticker", symbols)
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        // We only care about ticker messages
+        if v["type"] != "ticker" {
+            return Ok(vec![]);
+        }
+
+        let coinbase_message: CoinbaseMessage = serde_json::from_value(v)?;
+
+        match self.symbols.get("coinbase", &coinbase_message.product_id) {
+            Some(info) => Ok(vec![FeedEvent::Ticker(WsMessage::from_coinbase(
+                &info,
+                coinbase_message,
+            ))]),
+            None => {
+                warn!("Unknown Coinbase product: {}", coinbase_message.product_id);
+                Ok(vec![])
+            }
+        }
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        let infos = fetch_products(&self.api_url).await?;
+
+        let symbols = infos
+            .iter()
+            .filter(|info| info.status == "online" && self.symbols.allows(info))
+            .map(|info| info.venue_symbol.clone())
+            .collect();
+        self.symbols.replace("coinbase", infos);
+
+        Ok(symbols)
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(POLL_INTERVAL)
+    }
+
+    // Coinbase only has a per product ticker endpoint, so a cycle polls as
+    // many products as fit into the poll interval and the next one picks up
+    // where it stopped
+    async fn poll_tickers(&self, symbols: &[String]) -> Result<Vec<WsMessage>> {
+        let mut tickers = vec![];
+        if symbols.is_empty() {
+            return Ok(tickers);
+        }
+
+        let deadline = Instant::now() + POLL_INTERVAL - POLL_REQUEST_GAP;
+        let start = self.poll_cursor.load(Ordering::Relaxed) % symbols.len();
+        let mut polled = 0;
+
+        for symbol in symbols.iter().cycle().skip(start).take(symbols.len()) {
+            if polled > 0 {
+                if Instant::now() + POLL_REQUEST_GAP >= deadline {
+                    break;
+                }
+                sleep(POLL_REQUEST_GAP).await;
+            }
+            polled += 1;
+
+            let info = match self.symbols.get("coinbase", symbol) {
+                Some(info) => info,
+                None => continue,
+            };
+
+            let ticker = timeout_at(deadline, fetch_product_ticker(&self.api_url, symbol)).await;
+            match ticker {
+                Err(_) => {
+                    warn!("Polling Coinbase {} ticker timed out", symbol);
+                    break;
+                }
+                Ok(Ok(ticker)) => {
+                    let mut ws_message =
+                        WsMessage::new(&info.source, &info.base, &info.quote, ticker.price);
+                    ws_message.volume = ticker.volume;
+                    ws_message.best_bid = ticker.bid;
+                    ws_message.best_ask = ticker.ask;
+                    ws_message.event_time = ticker
+                        .time
+                        .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+                        .map(|time| time.timestamp_millis());
+
+                    tickers.push(ws_message);
+                }
+                Ok(Err(err)) => warn!("Failed to poll Coinbase {} ticker: {}", symbol, err),
+            }
+        }
+
+        self.poll_cursor
+            .store((start + polled) % symbols.len(), Ordering::Relaxed);
+
+        Ok(tickers)
+    }
+}
+
+/// Level 2 order books of the products listed in `ORDER_BOOK_SYMBOLS`.
+/// Coinbase sends a snapshot after subscribing, followed by updates.
+pub struct CoinbaseBookConnector {
+    ws_url: String,
+    symbols: Vec<String>,
+}
+
+impl CoinbaseBookConnector {
+    pub fn new(config: &Config) -> Self {
+        CoinbaseBookConnector {
+            ws_url: config.coinbase_ws_url.clone(),
+            symbols: config.order_book_symbols(),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for CoinbaseBookConnector {
+    fn name(&self) -> &'static str {
+        "Coinbase level2"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        channel_messages("subscribe", "level2_batch", symbols)
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        channel_messages("unsubscribe", "level2_batch", symbols)
+    }
+
+    fn serves_tickers(&self) -> bool {
+        false
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        let update = match v["type"].as_str() {
+            Some("snapshot") => {
+                let snapshot: Level2Snapshot = serde_json::from_value(v)?;
+
+                BookUpdate {
+                    source: "coinbase".to_string(),
+                    symbol: snapshot.product_id,
+                    kind: BookUpdateKind::Snapshot { sequence: None },
+                    bids: snapshot.bids,
+                    asks: snapshot.asks,
                 }
             }
-            Err(err) => {
-                warn!(
-                    "Failed to connect to WebSocket: {}. Retrying in 5 seconds...",
-                    err
-                );
+            Some("l2update") => {
+                let l2update: Level2Update = serde_json::from_value(v)?;
+                let (bids, asks): (Vec<_>, Vec<_>) = l2update
+                    .changes
+                    .into_iter()
+                    .partition(|(side, _, _)| side == "buy");
+
+                BookUpdate {
+                    source: "coinbase".to_string(),
+                    symbol: l2update.product_id,
+                    kind: BookUpdateKind::Diff {
+                        first: None,
+                        last: None,
+                    },
+                    bids: bids
+                        .into_iter()
+                        .map(|(_, price, size)| (price, size))
+                        .collect(),
+                    asks: asks
+                        .into_iter()
+                        .map(|(_, price, size)| (price, size))
+                        .collect(),
+                }
             }
+            _ => return Ok(vec![]),
+        };
+
+        Ok(vec![FeedEvent::Book(update)])
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        Ok(self.symbols.clone())
+    }
+}
+
+/// `matches` channel of the products listed in `TRADE_SYMBOLS`
+pub struct CoinbaseTradeConnector {
+    ws_url: String,
+    symbols: Vec<String>,
+}
+
+impl CoinbaseTradeConnector {
+    pub fn new(config: &Config) -> Self {
+        CoinbaseTradeConnector {
+            ws_url: config.coinbase_ws_url.clone(),
+            symbols: config.trade_symbols(),
         }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for CoinbaseTradeConnector {
+    fn name(&self) -> &'static str {
+        "Coinbase matches"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        channel_messages("subscribe", "matches", symbols)
+    }
+
+    fn unsubscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        channel_messages("unsubscribe", "matches", symbols)
+    }
+
+    fn serves_tickers(&self) -> bool {
+        false
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        // `last_match` is sent once after subscribing and is not a new trade
+        if v["type"] != "match" {
+            return Ok(vec![]);
+        }
+
+        let match_message: MatchMessage = serde_json::from_value(v)?;
+
+        // Taker is on the opposite side of the maker
+        let side = if match_message.side == "buy" {
+            TradeSide::Sell
+        } else {
+            TradeSide::Buy
+        };
+
+        let mut trade = Trade::new(
+            "coinbase",
+            &match_message.product_id,
+            match_message.trade_id.to_string(),
+            match_message.price,
+            match_message.size,
+            side,
+        );
+        trade.exchange_time = match_message
+            .time
+            .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+            .map(|time| time.timestamp_millis());
+
+        Ok(vec![FeedEvent::Trade(trade)])
+    }
 
-        // Wait 5 seconds trying to reconnect
-        sleep(Duration::from_secs(5)).await;
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        Ok(self.symbols.clone())
     }
 }
@@ -0,0 +1,428 @@
+use chrono::Utc;
+use log::{debug, warn};
+use redis::Client as RedisClient;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use tokio::time::{interval, Duration, Instant};
+
+use crate::config::Config;
+use crate::services::{connector::publish, ws_message::WsMessage};
+use crate::AppContext;
+
+pub const COMPOSITE_SOURCE: &str = "composite";
+
+/// How the prices of the sources are combined
+#[derive(Debug, Clone)]
+pub enum CompositeMethod {
+    Median,
+    /// Weighted by the 24h volume of each source
+    Volume,
+    /// Weighted by `COMPOSITE_WEIGHTS`
+    Weights(HashMap<String, Decimal>),
+}
+
+#[derive(Debug, Clone)]
+pub struct CompositeSettings {
+    pub method: CompositeMethod,
+    pub max_age_ms: i64,
+    /// Largest relative distance from the median of the sources, e.g. 0.01
+    pub max_deviation: Decimal,
+    pub min_sources: usize,
+}
+
+impl CompositeSettings {
+    pub fn new(config: &Config) -> Self {
+        let method = match config.composite_method.to_lowercase().as_str() {
+            "volume" => CompositeMethod::Volume,
+            "weights" => CompositeMethod::Weights(parse_weights(&config.composite_weights)),
+            "median" => CompositeMethod::Median,
+            method => {
+                warn!("Unknown composite method {}, using the median", method);
+                CompositeMethod::Median
+            }
+        };
+
+        CompositeSettings {
+            method,
+            max_age_ms: config.composite_max_age_secs as i64 * 1000,
+            max_deviation: Decimal::try_from(config.composite_max_deviation).unwrap_or_default(),
+            min_sources: config.composite_min_sources,
+        }
+    }
+}
+
+/// Comma separated `source:weight` entries
+fn parse_weights(value: &str) -> HashMap<String, Decimal> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let (source, weight) = entry.split_once(':')?;
+            match weight.trim().parse() {
+                Ok(weight) => Some((source.trim().to_lowercase(), weight)),
+                Err(_) => {
+                    warn!("Invalid composite weight {}", entry);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn median(prices: &mut [Decimal]) -> Option<Decimal> {
+    prices.sort();
+
+    let middle = prices.len() / 2;
+    match prices.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(prices[middle]),
+        _ => Some((prices[middle - 1] + prices[middle]) / Decimal::TWO),
+    }
+}
+
+/// Empty when no source has a weight, or on overflow
+fn weighted_mean(prices: &[(&WsMessage, Decimal)]) -> Option<Decimal> {
+    let total = prices
+        .iter()
+        .try_fold(Decimal::ZERO, |total, (_, weight)| {
+            total.checked_add(*weight)
+        })?;
+    if total <= Decimal::ZERO {
+        return None;
+    }
+
+    let sum = prices
+        .iter()
+        .try_fold(Decimal::ZERO, |sum, (ticker, weight)| {
+            sum.checked_add(ticker.price.checked_mul(*weight)?)
+        })?;
+
+    sum.checked_div(total)
+}
+
+fn volume(ticker: &WsMessage) -> Option<Decimal> {
+    ticker.volume.as_ref()?.parse().ok()
+}
+
+/// Combines the latest tickers of one instrument on several sources. Stale
+/// tickers and the ones too far from the median are left out.
+pub fn consolidate(
+    tickers: &[WsMessage],
+    now: i64,
+    settings: &CompositeSettings,
+) -> Option<WsMessage> {
+    let fresh = tickers
+        .iter()
+        .filter(|ticker| ticker.get_source() != COMPOSITE_SOURCE && !ticker.price.is_zero())
+        .filter(|ticker| {
+            ticker
+                .received_time
+                .or(ticker.event_time)
+                .map_or(false, |time| now - time <= settings.max_age_ms)
+        })
+        .collect::<Vec<_>>();
+
+    let reference = median(&mut fresh.iter().map(|ticker| ticker.price).collect::<Vec<_>>())?;
+    let kept = fresh
+        .into_iter()
+        .filter(|ticker| {
+            let deviation = ((ticker.price - reference) / reference).abs();
+            if deviation > settings.max_deviation {
+                debug!(
+                    "{} {} left out of the composite, {} from the median",
+                    ticker.get_source(),
+                    ticker.get_symbol(),
+                    deviation
+                );
+                return false;
+            }
+
+            true
+        })
+        .collect::<Vec<_>>();
+    if kept.len() < settings.min_sources {
+        return None;
+    }
+
+    let mut prices = kept.iter().map(|ticker| ticker.price).collect::<Vec<_>>();
+    let price = match &settings.method {
+        CompositeMethod::Median => median(&mut prices),
+        CompositeMethod::Volume => weighted_mean(
+            &kept
+                .iter()
+                .map(|ticker| (*ticker, volume(ticker).unwrap_or_default()))
+                .collect::<Vec<_>>(),
+        ),
+        // Sources without a weight don't count
+        CompositeMethod::Weights(weights) => weighted_mean(
+            &kept
+                .iter()
+                .map(|ticker| {
+                    let weight = weights.get(ticker.get_source()).copied();
+                    (*ticker, weight.unwrap_or_default())
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+    // Weighted methods fall back to the median when no source has a weight
+    .or_else(|| median(&mut prices))?;
+
+    let first = kept.first()?;
+    let mut composite = WsMessage::new(
+        COMPOSITE_SOURCE,
+        first.get_base(),
+        first.get_quote(),
+        price.normalize(),
+    );
+    let volumes = kept
+        .iter()
+        .filter_map(|ticker| volume(ticker))
+        .collect::<Vec<_>>();
+    if !volumes.is_empty() {
+        composite.volume = volumes
+            .into_iter()
+            .try_fold(Decimal::ZERO, |total, volume| total.checked_add(volume))
+            .map(|volume| volume.to_string());
+    }
+    composite.event_time = kept
+        .iter()
+        .filter_map(|ticker| ticker.received_time.or(ticker.event_time))
+        .max();
+    composite.sources = kept
+        .iter()
+        .map(|ticker| ticker.get_source().to_string())
+        .collect();
+    composite.degraded = kept.iter().any(|ticker| ticker.degraded);
+
+    Some(composite)
+}
+
+/// Publishes the composite ticker of every instrument listed on at least
+/// `COMPOSITE_MIN_SOURCES` sources, once a second
+pub async fn run_composite(app_context: AppContext) {
+    let settings = CompositeSettings::new(&app_context.config);
+
+    let mut redis_connection = match RedisClient::open(app_context.config.redis_url.as_str())
+        .and_then(|client| client.get_connection())
+    {
+        Ok(redis_connection) => redis_connection,
+        Err(err) => {
+            warn!("No composite prices: {}", err);
+            return;
+        }
+    };
+
+    let mut timer = interval(Duration::from_secs(1));
+
+    loop {
+        timer.tick().await;
+
+        let mut instruments: BTreeMap<String, Vec<WsMessage>> = BTreeMap::new();
+        for ticker in app_context.tickers.all() {
+            if ticker.get_source() != COMPOSITE_SOURCE {
+                instruments
+                    .entry(ticker.get_symbol())
+                    .or_default()
+                    .push(ticker);
+            }
+        }
+
+        let now = Utc::now().timestamp_millis();
+        for tickers in instruments.values() {
+            if tickers.len() < settings.min_sources {
+                continue;
+            }
+
+            if let Some(composite) = consolidate(tickers, now, &settings) {
+                if let Err(err) = publish(
+                    &app_context,
+                    &mut redis_connection,
+                    composite,
+                    Instant::now(),
+                ) {
+                    warn!("Failed to publish composite price: {}", err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const NOW: i64 = 1_700_000_000_000;
+
+    fn decimal(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn ticker(source: &str, price: &str, age_ms: i64) -> WsMessage {
+        let mut ticker = WsMessage::new(source, "BTC", "USDT", decimal(price));
+        ticker.received_time = Some(NOW - age_ms);
+        ticker
+    }
+
+    fn settings(method: CompositeMethod) -> CompositeSettings {
+        CompositeSettings {
+            method,
+            max_age_ms: 30_000,
+            max_deviation: decimal("0.01"),
+            min_sources: 2,
+        }
+    }
+
+    #[test]
+    fn median_of_odd_and_even_counts() {
+        let cases: [(&[&str], Option<&str>); 5] = [
+            (&[], None),
+            (&["5"], Some("5")),
+            (&["3", "1", "2"], Some("2")),
+            (&["4", "1", "3", "2"], Some("2.5")),
+            (&["1.1", "1.2"], Some("1.15")),
+        ];
+
+        for (prices, expected) in cases {
+            let mut prices = prices
+                .iter()
+                .map(|price| decimal(price))
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                median(&mut prices),
+                expected.map(decimal),
+                "median of {:?}",
+                prices
+            );
+        }
+    }
+
+    #[test]
+    fn weighted_mean_cases() {
+        let a = ticker("binance", "100", 0);
+        let b = ticker("coinbase", "200", 0);
+        let huge = WsMessage::new("kraken", "BTC", "USDT", Decimal::MAX);
+
+        let cases = [
+            (vec![], None),
+            (vec![(&a, decimal("1")), (&b, decimal("1"))], Some("150")),
+            (vec![(&a, decimal("3")), (&b, decimal("1"))], Some("125")),
+            // A source without weight doesn't count
+            (vec![(&a, decimal("2")), (&b, Decimal::ZERO)], Some("100")),
+            (vec![(&a, Decimal::ZERO), (&b, Decimal::ZERO)], None),
+            (vec![(&huge, decimal("2")), (&a, decimal("1"))], None),
+        ];
+
+        for (prices, expected) in cases {
+            assert_eq!(weighted_mean(&prices), expected.map(decimal));
+        }
+    }
+
+    #[test]
+    fn consolidate_leaves_out_outliers_and_stale_sources() {
+        let settings = settings(CompositeMethod::Median);
+
+        // (tickers, composite price, sources)
+        let cases: [(Vec<WsMessage>, Option<&str>, &[&str]); 6] = [
+            (
+                vec![ticker("binance", "100", 0), ticker("coinbase", "102", 0)],
+                Some("101"),
+                &["binance", "coinbase"],
+            ),
+            // Exactly 1% off the median of 100 is kept, more is cut off
+            (
+                vec![
+                    ticker("binance", "100", 0),
+                    ticker("coinbase", "101", 0),
+                    ticker("kraken", "99", 0),
+                    ticker("okx", "99.9", 0),
+                    ticker("bybit", "120", 0),
+                ],
+                Some("99.95"),
+                &["binance", "coinbase", "kraken", "okx"],
+            ),
+            (
+                vec![
+                    ticker("binance", "100", 0),
+                    ticker("coinbase", "100.2", 0),
+                    ticker("kraken", "100.1", 30_001),
+                ],
+                Some("100.1"),
+                &["binance", "coinbase"],
+            ),
+            // Not enough sources once the stale one is gone
+            (
+                vec![
+                    ticker("binance", "100", 0),
+                    ticker("coinbase", "100", 60_000),
+                ],
+                None,
+                &[],
+            ),
+            (
+                vec![
+                    ticker("binance", "100", 0),
+                    ticker(COMPOSITE_SOURCE, "100", 0),
+                ],
+                None,
+                &[],
+            ),
+            (
+                vec![ticker("binance", "100", 0), ticker("coinbase", "0", 0)],
+                None,
+                &[],
+            ),
+        ];
+
+        for (tickers, price, sources) in cases {
+            let composite = consolidate(&tickers, NOW, &settings);
+
+            assert_eq!(
+                composite.as_ref().map(|composite| composite.price),
+                price.map(decimal),
+                "{:?}",
+                tickers
+            );
+            if let Some(composite) = composite {
+                assert_eq!(composite.get_source(), COMPOSITE_SOURCE);
+                assert_eq!(composite.sources, *sources);
+            }
+        }
+    }
+
+    #[test]
+    fn consolidate_weights() {
+        let mut tickers = vec![
+            ticker("binance", "100", 0),
+            ticker("coinbase", "101", 1_000),
+        ];
+        tickers[0].volume = Some("3".to_string());
+        tickers[1].volume = Some("1".to_string());
+
+        let composite = consolidate(&tickers, NOW, &settings(CompositeMethod::Volume)).unwrap();
+        assert_eq!(composite.price, decimal("100.25"));
+        assert_eq!(composite.volume.as_deref(), Some("4"));
+        assert_eq!(composite.event_time, Some(NOW));
+
+        let weights = HashMap::from([("coinbase".to_string(), decimal("1"))]);
+        let composite =
+            consolidate(&tickers, NOW, &settings(CompositeMethod::Weights(weights))).unwrap();
+        assert_eq!(composite.price, decimal("101"));
+
+        // No source has a weight, the median is used instead
+        let weights = HashMap::from([("kraken".to_string(), decimal("1"))]);
+        let composite =
+            consolidate(&tickers, NOW, &settings(CompositeMethod::Weights(weights))).unwrap();
+        assert_eq!(composite.price, decimal("100.5"));
+    }
+
+    #[test]
+    fn degraded_sources_mark_the_composite() {
+        let mut tickers = vec![ticker("binance", "100", 0), ticker("coinbase", "100", 0)];
+        tickers[1].degraded = true;
+
+        let composite = consolidate(&tickers, NOW, &settings(CompositeMethod::Median)).unwrap();
+
+        assert!(composite.degraded);
+    }
+}
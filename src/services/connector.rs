@@ -0,0 +1,753 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use eyre::{bail, Result, WrapErr};
+use futures_util::{Sink, SinkExt, StreamExt};
+use log::{info, warn};
+use redis::{
+    Client as RedisClient, Commands, Connection, ExistenceCheck, SetExpiry, SetOptions, Value,
+};
+use serde::Serialize;
+use std::{collections::HashSet, future::pending, sync::Arc};
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, mpsc, watch},
+    time::{interval_at, sleep, sleep_until, Duration, Instant, Interval},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{protocol::Message, Error as WsError},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::services::{
+    composite::COMPOSITE_SOURCE,
+    latency::LatencyStage,
+    order_book::{BookStatus, BookUpdate},
+    reconnect::ReconnectPolicy,
+    supervisor::{FeedHandle, FeedState},
+    trade::Trade,
+    ws_message::WsMessage,
+};
+use crate::AppContext;
+
+/// Least time between two snapshot requests of the same order book, deep
+/// snapshots weigh heavily on the venue request limits
+const SNAPSHOT_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Fetched snapshots waiting to be applied by the connection
+const SNAPSHOT_QUEUE: usize = 64;
+
+/// Order books fed by one connection
+#[derive(Default)]
+pub struct FeedBooks {
+    /// Keys of the books, they can't be trusted once the connection drops
+    pub keys: HashSet<(String, String)>,
+    /// Symbols of out of sync books whose snapshot is due. Fetching them is
+    /// up to the caller, so that a slow venue API doesn't hold up the frames.
+    pub snapshots: Vec<String>,
+}
+
+/// Market data decoded from a single frame
+#[derive(Debug)]
+pub enum FeedEvent {
+    Ticker(WsMessage),
+    Book(BookUpdate),
+    Trade(Trade),
+}
+
+/// Venue specific part of a market data feed. Everything else (reconnecting,
+/// deduplication through Redis and broadcasting to the websocket clients)
+/// is handled by `run_connector`.
+#[async_trait]
+pub trait ExchangeConnector: Send + Sync {
+    /// Name of the venue, used in logs
+    fn name(&self) -> &'static str;
+
+    /// WebSocket URL to connect to for the given symbols
+    fn url(&self, symbols: &[String]) -> String;
+
+    /// Messages sent right after the connection is established, and when
+    /// symbols are added to a live connection
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>>;
+
+    /// Messages sent when symbols are removed from a live connection
+    fn unsubscribe_messages(&self, _symbols: &[String]) -> Result<Vec<Message>> {
+        Ok(vec![])
+    }
+
+    /// Decodes a text frame into market data events, frames which carry no
+    /// market data (acks, heartbeats, etc.) decode into an empty list
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>>;
+
+    /// Symbols which are available on the venue
+    async fn fetch_symbols(&self) -> Result<Vec<String>>;
+
+    /// Whether the feed publishes tickers, as opposed to order books or trades
+    fn serves_tickers(&self) -> bool {
+        true
+    }
+
+    /// Full order book, for venues whose depth feed only sends diffs
+    async fn fetch_book_snapshot(&self, _symbol: &str) -> Result<BookUpdate> {
+        bail!("{} does not provide order book snapshots", self.name())
+    }
+
+    /// How often to send `ping_message`, for venues which require pings more
+    /// often than the configured heartbeat interval
+    fn ping_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    fn ping_message(&self) -> Message {
+        Message::Ping(vec![])
+    }
+
+    /// Limit of control messages (subscriptions, pings) the venue accepts
+    fn max_messages_per_second(&self) -> Option<u32> {
+        None
+    }
+
+    /// How often to poll `poll_tickers` while the feed is down, for venues
+    /// with a REST fallback
+    fn poll_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Latest tickers of the symbols over REST
+    async fn poll_tickers(&self, _symbols: &[String]) -> Result<Vec<WsMessage>> {
+        bail!("{} has no REST fallback", self.name())
+    }
+}
+
+/// Fetches the venue symbols in the background, retrying with backoff until
+/// the venue answers, and then re-fetches them every `refresh_interval` so
+/// that connections pick up new listings and drop delisted symbols without a
+/// restart. The list stays empty until the first fetch succeeds.
+pub fn watch_symbols(
+    connector: Arc<dyn ExchangeConnector>,
+    retry_policy: ReconnectPolicy,
+    refresh_interval: Option<Duration>,
+) -> watch::Receiver<Vec<String>> {
+    let (symbols_tx, symbols_rx) = watch::channel(vec![]);
+
+    tokio::task::spawn(async move {
+        // Venues may be unreachable for a while, never give up on them
+        let mut backoff = ReconnectPolicy {
+            max_attempts: None,
+            ..retry_policy.clone()
+        }
+        .backoff();
+
+        loop {
+            match connector.fetch_symbols().await {
+                Ok(symbols) => {
+                    info!("{} lists {} symbols", connector.name(), symbols.len());
+                    symbols_tx.send_replace(symbols);
+                    break;
+                }
+                Err(err) => {
+                    let delay = backoff.next_delay().unwrap_or(retry_policy.max_delay);
+                    warn!(
+                        "Failed to fetch {} symbols, retrying in {:.1} seconds: {:#}",
+                        connector.name(),
+                        delay.as_secs_f64(),
+                        err
+                    );
+                    sleep(delay).await;
+                }
+            }
+
+            if symbols_tx.is_closed() {
+                return;
+            }
+        }
+
+        let refresh_interval = match refresh_interval {
+            Some(refresh_interval) => refresh_interval,
+            None => return,
+        };
+        let mut refresh_timer = interval_at(Instant::now() + refresh_interval, refresh_interval);
+
+        while !symbols_tx.is_closed() {
+            refresh_timer.tick().await;
+
+            match connector.fetch_symbols().await {
+                Ok(mut latest) => {
+                    latest.sort();
+                    symbols_tx.send_if_modified(|current| {
+                        current.sort();
+                        if *current == latest {
+                            return false;
+                        }
+
+                        info!("{} symbols changed", connector.name());
+                        *current = latest;
+                        true
+                    });
+                }
+                Err(err) => {
+                    warn!("Failed to refresh {} symbols: {}", connector.name(), err);
+                }
+            }
+        }
+    });
+
+    symbols_rx
+}
+
+/// Spreads the symbols over connections of at most `per_connection` symbols
+/// and keeps them balanced as the list changes. Symbols stay on their
+/// connection until they are removed, new ones fill free slots first and
+/// `spawn_shard` is called when another connection is needed.
+pub async fn run_shards(
+    mut symbols: watch::Receiver<Vec<String>>,
+    per_connection: usize,
+    mut spawn_shard: impl FnMut(watch::Receiver<Vec<String>>),
+) {
+    let mut shards: Vec<watch::Sender<Vec<String>>> = vec![];
+
+    loop {
+        let wanted = symbols
+            .borrow_and_update()
+            .iter()
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        let mut assigned = HashSet::new();
+        let mut removed = 0;
+        for shard in &shards {
+            shard.send_if_modified(|shard_symbols| {
+                let before = shard_symbols.len();
+                shard_symbols.retain(|symbol| wanted.contains(symbol));
+                assigned.extend(shard_symbols.iter().cloned());
+
+                removed += before - shard_symbols.len();
+                shard_symbols.len() != before
+            });
+        }
+
+        let mut added = wanted.difference(&assigned).cloned().collect::<Vec<_>>();
+        added.sort();
+        let added_count = added.len();
+        let mut added = added.into_iter().peekable();
+
+        for shard in &shards {
+            if added.peek().is_none() {
+                break;
+            }
+
+            shard.send_if_modified(|shard_symbols| {
+                let before = shard_symbols.len();
+                let free = per_connection.saturating_sub(before);
+                shard_symbols.extend(added.by_ref().take(free));
+
+                shard_symbols.len() != before
+            });
+        }
+
+        while added.peek().is_some() {
+            let (shard_tx, shard_rx) =
+                watch::channel(added.by_ref().take(per_connection).collect());
+            spawn_shard(shard_rx);
+            shards.push(shard_tx);
+        }
+
+        info!(
+            "Symbols sharded over {} connections: {} added, {} removed",
+            shards.len(),
+            added_count,
+            removed
+        );
+
+        if symbols.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+pub async fn run_connector(
+    app_context: AppContext,
+    connector: Arc<dyn ExchangeConnector>,
+    mut symbols: watch::Receiver<Vec<String>>,
+    reconnect_policy: ReconnectPolicy,
+    feed: FeedHandle,
+) -> Result<()> {
+    let mut redis_connection =
+        RedisClient::open(app_context.config.redis_url.as_str())?.get_connection()?;
+
+    let mut backoff = reconnect_policy.backoff();
+
+    // 0 turns pings and the stale check off
+    let heartbeat_interval = match app_context.config.heartbeat_interval_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let stale_timeout = match app_context.config.stale_timeout_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    // Discovered symbols only arrive once the venue answered
+    while symbols.borrow().is_empty() {
+        if symbols.changed().await.is_err() {
+            info!("No {} symbols to subscribe to", connector.name());
+            return Ok(());
+        }
+    }
+
+    // Connectors with a fixed symbol list drop the sender straight away
+    let mut symbols_watched = true;
+
+    loop {
+        let mut subscribed = symbols.borrow_and_update().clone();
+        let url = connector.url(&subscribed);
+        feed.set_state(FeedState::Connecting);
+
+        match connect(connector.as_ref(), &url, &subscribed).await {
+            Ok(mut ws_stream) => {
+                info!("Connected to {} WebSocket", connector.name());
+                backoff.reset();
+                feed.set_state(FeedState::Live);
+
+                let mut ping_timer = connector
+                    .ping_interval()
+                    .or(heartbeat_interval)
+                    .map(|ping_period| interval_at(Instant::now() + ping_period, ping_period));
+
+                // Any frame, including pongs, proves that the socket is alive
+                let mut last_message = Instant::now();
+
+                let mut books = FeedBooks::default();
+
+                // Snapshots are fetched next to the connection and applied by it,
+                // the books buffer diffs in the meantime
+                let (snapshot_tx, mut snapshot_rx) = mpsc::channel(SNAPSHOT_QUEUE);
+
+                loop {
+                    tokio::select! {
+                        message = ws_stream.next() => {
+                            if let Some(Ok(_)) = message {
+                                last_message = Instant::now();
+                            }
+
+                            match message {
+                                Some(Ok(Message::Text(data))) => {
+                                    if let Some(recorder) = &app_context.recorder {
+                                        recorder.record(connector.name(), &data);
+                                    }
+
+                                    handle_frame(
+                                        &app_context,
+                                        &mut redis_connection,
+                                        connector.as_ref(),
+                                        &data,
+                                        &mut books,
+                                    )?;
+
+                                    for symbol in books.snapshots.drain(..) {
+                                        spawn_snapshot(connector.clone(), symbol, &snapshot_tx);
+                                    }
+                                }
+                                Some(Ok(Message::Close(_))) => {
+                                    warn!("{} WebSocket connection closed", connector.name());
+                                    break;
+                                }
+                                Some(Ok(_)) => {}
+                                Some(Err(_)) | None => break,
+                            }
+                        }
+                        Some((symbol, snapshot)) = snapshot_rx.recv() => {
+                            apply_snapshot(&app_context, connector.as_ref(), &symbol, snapshot);
+                        }
+                        _ = sleep_until(last_message + stale_timeout.unwrap_or_default()),
+                            if stale_timeout.is_some() =>
+                        {
+                            warn!(
+                                "{} WebSocket is stale, no data for {:.1} seconds, reconnecting",
+                                connector.name(),
+                                last_message.elapsed().as_secs_f64()
+                            );
+                            feed.set_state(FeedState::Stale);
+                            break;
+                        }
+                        _ = tick(&mut ping_timer) => {
+                            if let Err(err) = ws_stream.send(connector.ping_message()).await {
+                                warn!("Failed to ping {} WebSocket: {}", connector.name(), err);
+                                break;
+                            }
+                        }
+                        changed = symbols.changed(), if symbols_watched => {
+                            if changed.is_err() {
+                                symbols_watched = false;
+                                continue;
+                            }
+
+                            let latest = symbols.borrow_and_update().clone();
+
+                            // Symbols are part of the URL, so the only way is to reconnect
+                            if connector.url(&latest) != url {
+                                info!("{} symbols changed, reconnecting", connector.name());
+                                break;
+                            }
+
+                            let current = subscribed.iter().collect::<HashSet<_>>();
+                            let wanted = latest.iter().collect::<HashSet<_>>();
+                            let added = latest
+                                .iter()
+                                .filter(|symbol| !current.contains(symbol))
+                                .cloned()
+                                .collect::<Vec<_>>();
+                            let removed = subscribed
+                                .iter()
+                                .filter(|symbol| !wanted.contains(symbol))
+                                .cloned()
+                                .collect::<Vec<_>>();
+
+                            info!(
+                                "Updating {} subscriptions: {} added, {} removed",
+                                connector.name(),
+                                added.len(),
+                                removed.len()
+                            );
+
+                            let resubscribed = resubscribe(
+                                &mut ws_stream,
+                                connector.as_ref(),
+                                &added,
+                                &removed,
+                            )
+                            .await;
+                            if let Err(err) = resubscribed {
+                                warn!(
+                                    "Failed to update {} subscriptions, reconnecting: {:#}",
+                                    connector.name(),
+                                    err
+                                );
+                                feed.set_error(format!("Failed to resubscribe: {:#}", err));
+                                break;
+                            }
+
+                            subscribed = latest;
+                        }
+                    }
+                }
+
+                for (source, symbol) in books.keys {
+                    app_context.order_books.invalidate(&source, &symbol);
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to connect to {} WebSocket: {:#}",
+                    connector.name(),
+                    err
+                );
+                feed.set_error(format!("Failed to connect: {:#}", err));
+            }
+        }
+
+        match backoff.next_delay() {
+            Some(delay) => {
+                info!(
+                    "Reconnecting to {} WebSocket in {:.1} seconds",
+                    connector.name(),
+                    delay.as_secs_f64()
+                );
+                sleep(delay).await;
+            }
+            None => {
+                feed.set_state(FeedState::Dead);
+                bail!(
+                    "{} feed is dead after {} reconnect attempts",
+                    connector.name(),
+                    backoff.attempts()
+                )
+            }
+        }
+    }
+}
+
+/// Decodes a text frame and passes its events on to the stores and the
+/// websocket clients. The books which were fed and the snapshots they need
+/// end up in `books`. Frames which fail to decode go to the dead letters.
+pub fn handle_frame(
+    app_context: &AppContext,
+    redis_connection: &mut Connection,
+    connector: &dyn ExchangeConnector,
+    data: &str,
+    books: &mut FeedBooks,
+) -> Result<()> {
+    let received_at = Instant::now();
+    let received_time = Utc::now().timestamp_millis();
+
+    // A bad frame should not take the connection down with it
+    let events = match connector.decode(data) {
+        Ok(events) => events,
+        Err(err) => {
+            app_context.dead_letters.push(connector.name(), data, &err);
+            return Ok(());
+        }
+    };
+
+    for event in events {
+        match event {
+            FeedEvent::Ticker(mut ws_message) => {
+                ws_message.received_time = Some(received_time);
+
+                publish(app_context, redis_connection, ws_message, received_at)
+                    .wrap_err_with(|| format!("Failed to send {} message", connector.name()))?;
+            }
+            FeedEvent::Book(update) => {
+                books
+                    .keys
+                    .insert((update.source.clone(), update.symbol.clone()));
+                apply_book_update(app_context, update, books);
+            }
+            FeedEvent::Trade(trade) => publish_trade(app_context, &trade)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Connects and subscribes to the symbols
+async fn connect(
+    connector: &dyn ExchangeConnector,
+    url: &str,
+    symbols: &[String],
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let (mut ws_stream, _) = connect_async(url).await?;
+
+    if !symbols.is_empty() {
+        let messages = connector.subscribe_messages(symbols)?;
+        send_throttled(&mut ws_stream, messages, connector)
+            .await
+            .wrap_err("Failed to subscribe")?;
+    }
+
+    Ok(ws_stream)
+}
+
+/// Moves a live connection over to the added and removed symbols
+async fn resubscribe<S>(
+    ws_stream: &mut S,
+    connector: &dyn ExchangeConnector,
+    added: &[String],
+    removed: &[String],
+) -> Result<()>
+where
+    S: Sink<Message, Error = WsError> + Unpin,
+{
+    let mut messages = vec![];
+    if !removed.is_empty() {
+        messages.extend(connector.unsubscribe_messages(removed)?);
+    }
+    if !added.is_empty() {
+        messages.extend(connector.subscribe_messages(added)?);
+    }
+
+    send_throttled(ws_stream, messages, connector).await
+}
+
+/// Ticks of an optional timer, never when there is none
+async fn tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => pending().await,
+    }
+}
+
+/// Sends messages one by one, keeping within the venue message rate limit
+async fn send_throttled<S>(
+    ws_stream: &mut S,
+    messages: Vec<Message>,
+    connector: &dyn ExchangeConnector,
+) -> Result<()>
+where
+    S: Sink<Message, Error = WsError> + Unpin,
+{
+    let gap = connector
+        .max_messages_per_second()
+        .map(|rate| Duration::from_secs(1) / rate.max(1));
+
+    for (i, message) in messages.into_iter().enumerate() {
+        if let (Some(gap), true) = (gap, i > 0) {
+            sleep(gap).await;
+        }
+        ws_stream.send(message).await?;
+    }
+
+    Ok(())
+}
+
+/// Applies a book update and sends the top of the book to the websocket
+/// clients. Out of sync books get their symbol added to `books.snapshots`
+/// when a snapshot is due, diffs are buffered by the book until it lines up.
+fn apply_book_update(app_context: &AppContext, update: BookUpdate, books: &mut FeedBooks) {
+    let source = update.source.clone();
+    let symbol = update.symbol.clone();
+
+    match app_context.order_books.apply(update) {
+        BookStatus::Updated => send_book(app_context, &source, &symbol),
+        BookStatus::OutOfSync => {
+            if app_context
+                .order_books
+                .request_snapshot(&source, &symbol, SNAPSHOT_RETRY_INTERVAL)
+            {
+                info!(
+                    "{} {} order book out of sync, fetching snapshot",
+                    source, symbol
+                );
+                books.snapshots.push(symbol);
+            }
+        }
+        BookStatus::Skipped => {}
+    }
+}
+
+/// Fetches a snapshot in the background and sends it back to the connection
+fn spawn_snapshot(
+    connector: Arc<dyn ExchangeConnector>,
+    symbol: String,
+    snapshot_tx: &mpsc::Sender<(String, Result<BookUpdate>)>,
+) {
+    let snapshot_tx = snapshot_tx.clone();
+
+    tokio::task::spawn(async move {
+        let snapshot = connector.fetch_book_snapshot(&symbol).await;
+
+        // Nobody receives once the connection dropped
+        let _ = snapshot_tx.send((symbol, snapshot)).await;
+    });
+}
+
+/// Applies a snapshot requested through `FeedBooks::snapshots`
+pub fn apply_snapshot(
+    app_context: &AppContext,
+    connector: &dyn ExchangeConnector,
+    symbol: &str,
+    snapshot: Result<BookUpdate>,
+) {
+    let snapshot = match snapshot {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            warn!(
+                "Failed to fetch {} {} order book, retrying in {} seconds: {:#}",
+                connector.name(),
+                symbol,
+                SNAPSHOT_RETRY_INTERVAL.as_secs(),
+                err
+            );
+            return;
+        }
+    };
+
+    let source = snapshot.source.clone();
+    if app_context.order_books.apply(snapshot) == BookStatus::Updated {
+        send_book(app_context, &source, symbol);
+    } else {
+        info!(
+            "{} {} order book still out of sync, retrying in {} seconds",
+            source,
+            symbol,
+            SNAPSHOT_RETRY_INTERVAL.as_secs()
+        );
+    }
+}
+
+/// Sends the top of a book to the websocket clients
+fn send_book(app_context: &AppContext, source: &str, symbol: &str) {
+    let depth = app_context.config.order_book_depth;
+    if let Some(view) = app_context.order_books.top(source, symbol, depth) {
+        if let Err(err) = broadcast(&app_context.book_tx, &view) {
+            warn!("Failed to send order book: {}", err);
+        }
+    }
+}
+
+fn publish_trade(app_context: &AppContext, trade: &Trade) -> Result<()> {
+    app_context.candles.record_trade(trade);
+
+    info!("Sending trade to the ws client {}", trade);
+    broadcast(&app_context.trade_tx, trade)
+}
+
+/// Sends a value to the websocket clients of a channel
+pub fn broadcast<T: Serialize>(tx: &broadcast::Sender<Message>, value: &T) -> Result<()> {
+    let text = serde_json::to_string(value)?;
+
+    // Nobody listening is not an error
+    let _ = tx.send(Message::Text(text));
+
+    Ok(())
+}
+
+pub fn publish(
+    app_context: &AppContext,
+    redis_connection: &mut Connection,
+    mut ws_message: WsMessage,
+    received_at: Instant,
+) -> Result<()> {
+    // Composite tickers are computed here rather than received, and the
+    // times of polled tickers are those of the REST requests
+    let composite = ws_message.get_source() == COMPOSITE_SOURCE;
+
+    if let (Some(event_time), Some(received_time)) =
+        (ws_message.event_time, ws_message.received_time)
+    {
+        if !composite && !ws_message.degraded {
+            app_context.latency.record(
+                ws_message.get_source(),
+                LatencyStage::ExchangeToReceive,
+                (received_time - event_time) as f64,
+            );
+        }
+    }
+
+    let redis_result: Result<Value, redis::RedisError> = redis_connection.set_options(
+        ws_message.get_key(),
+        ws_message.price.to_string(),
+        SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .get(true)
+            .with_expiration(SetExpiry::EX(20)),
+    );
+
+    // Only send value, when it's not a cache hit
+    let send = match redis_result {
+        Ok(value) => value == Value::Nil,
+        Err(err) => {
+            warn!("Error setting cache: {}", err);
+            false
+        }
+    };
+
+    if send {
+        ws_message.published_time = Some(Utc::now().timestamp_millis());
+    }
+
+    app_context.tickers.update(&ws_message);
+    app_context.candles.record_tick(&ws_message);
+
+    if send {
+        let ws_message_string = serde_json::to_string(&ws_message)?;
+
+        info!("Sending value to the ws client {}", ws_message);
+        app_context
+            .ticker_tx
+            .send(Message::Text(ws_message_string))?;
+        app_context.prices.record(&ws_message);
+
+        if !composite {
+            app_context.latency.record(
+                ws_message.get_source(),
+                LatencyStage::ReceiveToPublish,
+                received_at.elapsed().as_secs_f64() * 1000.0,
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use async_graphql::SimpleObject;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::services::{composite::COMPOSITE_SOURCE, ws_message::WsMessage};
+
+/// One conversion of a cross rate path
+#[derive(Serialize, Clone, Debug, SimpleObject)]
+pub struct Hop {
+    pub source: String,
+    /// Converted from
+    pub from: String,
+    /// Converted to
+    pub to: String,
+    /// Units of `to` per unit of `from`
+    pub rate: Decimal,
+    /// Whether the ticker is listed as `to-from` and its price was inverted
+    pub inverted: bool,
+    /// Age of the ticker in milliseconds
+    pub age_ms: i64,
+}
+
+/// Rate between two assets, direct or through other assets
+#[derive(Serialize, Clone, Debug, SimpleObject)]
+pub struct CrossRate {
+    pub base: String,
+    pub quote: String,
+    pub price: Decimal,
+    pub path: Vec<Hop>,
+    /// Age of the oldest ticker on the path in milliseconds
+    pub staleness_ms: i64,
+}
+
+/// Best ticker to convert `from` into `to`
+struct Edge<'a> {
+    ticker: &'a WsMessage,
+    inverted: bool,
+    /// Only picks between listings of the pair, paths don't weigh it
+    liquidity: Decimal,
+    age_ms: i64,
+}
+
+/// Conversion graph of the latest tickers of every source
+pub struct CrossRates<'a> {
+    // from -> to -> edge
+    edges: HashMap<&'a str, HashMap<&'a str, Edge<'a>>>,
+}
+
+impl<'a> CrossRates<'a> {
+    /// Tickers older than `max_age_ms` are left out, and so are composite
+    /// tickers, which are combined from the venue tickers already in the
+    /// graph. Listings of the same pair on several sources keep the most
+    /// liquid one, the freshest one when liquidity is the same. Liquidity is
+    /// only a tiebreak between these parallel listings, it plays no part in
+    /// picking a path in `rate`.
+    pub fn new(tickers: &'a [WsMessage], now: i64, max_age_ms: i64) -> Self {
+        let mut edges: HashMap<&str, HashMap<&str, Edge>> = HashMap::new();
+
+        for ticker in tickers {
+            if ticker.get_source() == COMPOSITE_SOURCE {
+                continue;
+            }
+
+            let age_ms = match ticker.received_time.or(ticker.event_time) {
+                Some(time) => (now - time).max(0),
+                None => continue,
+            };
+            if age_ms > max_age_ms || ticker.price.is_zero() {
+                continue;
+            }
+
+            // 24h volume in base currency, only comparable between listings
+            // of the same pair
+            let liquidity = ticker
+                .volume
+                .as_ref()
+                .and_then(|volume| volume.parse().ok())
+                .unwrap_or_default();
+
+            for inverted in [false, true] {
+                let (from, to) = if inverted {
+                    (ticker.get_quote(), ticker.get_base())
+                } else {
+                    (ticker.get_base(), ticker.get_quote())
+                };
+                let edge = Edge {
+                    ticker,
+                    inverted,
+                    liquidity,
+                    age_ms,
+                };
+
+                let best = edges.entry(from).or_default().entry(to).or_insert(edge);
+                if (liquidity, Reverse(age_ms)) > (best.liquidity, Reverse(best.age_ms)) {
+                    *best = Edge {
+                        ticker,
+                        inverted,
+                        liquidity,
+                        age_ms,
+                    };
+                }
+            }
+        }
+
+        CrossRates { edges }
+    }
+
+    /// Rate of `base` in `quote` through at most `max_hops` conversions. The
+    /// path with the fewest hops wins, then the one with the freshest
+    /// oldest ticker, regardless of the liquidity of its listings.
+    pub fn rate(&self, base: &str, quote: &str, max_hops: usize) -> Option<CrossRate> {
+        if base == quote {
+            return Some(CrossRate {
+                base: base.to_string(),
+                quote: quote.to_string(),
+                price: Decimal::ONE,
+                path: vec![],
+                staleness_ms: 0,
+            });
+        }
+
+        // Dijkstra on (hops, staleness), both only grow along a path
+        let mut best: HashMap<&str, (usize, i64)> = HashMap::new();
+        let mut previous: HashMap<&str, (&str, &Edge)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        best.insert(base, (0, 0));
+        queue.push(Reverse((0, 0, base)));
+
+        while let Some(Reverse((hops, staleness, asset))) = queue.pop() {
+            if asset == quote {
+                break;
+            }
+            if best.get(asset) != Some(&(hops, staleness)) || hops >= max_hops {
+                continue;
+            }
+
+            for (to, edge) in self.edges.get(asset).into_iter().flatten() {
+                let label = (hops + 1, staleness.max(edge.age_ms));
+
+                if best.get(to).map_or(true, |current| label < *current) {
+                    best.insert(to, label);
+                    previous.insert(to, (asset, edge));
+                    queue.push(Reverse((label.0, label.1, to)));
+                }
+            }
+        }
+
+        let (_, staleness_ms) = *best.get(quote)?;
+
+        let mut path = vec![];
+        let mut asset = quote;
+        while asset != base {
+            let (from, edge) = previous.get(asset)?;
+            path.push(hop(from, asset, edge)?);
+            asset = from;
+        }
+        path.reverse();
+
+        let price = path
+            .iter()
+            .try_fold(Decimal::ONE, |price, hop| price.checked_mul(hop.rate))?;
+
+        Some(CrossRate {
+            base: base.to_string(),
+            quote: quote.to_string(),
+            // Direct quotes keep the precision the venue sent
+            price: if path.len() == 1 && !path[0].inverted {
+                path[0].rate
+            } else {
+                price.normalize()
+            },
+            path,
+            staleness_ms,
+        })
+    }
+}
+
+fn hop(from: &str, to: &str, edge: &Edge) -> Option<Hop> {
+    let rate = if edge.inverted {
+        Decimal::ONE.checked_div(edge.ticker.price)?
+    } else {
+        edge.ticker.price
+    };
+
+    Some(Hop {
+        source: edge.ticker.get_source().to_string(),
+        from: from.to_string(),
+        to: to.to_string(),
+        rate,
+        inverted: edge.inverted,
+        age_ms: edge.age_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const NOW: i64 = 1_700_000_000_000;
+    const MAX_AGE_MS: i64 = 60_000;
+
+    fn ticker(source: &str, symbol: &str, price: &str, age_ms: i64) -> WsMessage {
+        let (base, quote) = symbol.split_once('-').unwrap();
+        let mut ticker = WsMessage::new(source, base, quote, Decimal::from_str(price).unwrap());
+        ticker.received_time = Some(NOW - age_ms);
+        ticker
+    }
+
+    fn with_volume(mut ticker: WsMessage, volume: &str) -> WsMessage {
+        ticker.volume = Some(volume.to_string());
+        ticker
+    }
+
+    fn decimal(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    /// `source:from>to` of every hop
+    fn route(rate: &CrossRate) -> Vec<String> {
+        rate.path
+            .iter()
+            .map(|hop| format!("{}:{}>{}", hop.source, hop.from, hop.to))
+            .collect()
+    }
+
+    #[test]
+    fn direct_listing_keeps_the_venue_price() {
+        let tickers = [ticker("binance", "BTC-USDT", "30000.10", 5)];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        let rate = rates.rate("BTC", "USDT", 3).unwrap();
+
+        assert_eq!(rate.price.to_string(), "30000.10");
+        assert_eq!(route(&rate), vec!["binance:BTC>USDT"]);
+        assert!(!rate.path[0].inverted);
+        assert_eq!(rate.staleness_ms, 5);
+    }
+
+    #[test]
+    fn same_asset_is_one() {
+        let rates = CrossRates::new(&[], NOW, MAX_AGE_MS);
+
+        let rate = rates.rate("BTC", "BTC", 3).unwrap();
+
+        assert_eq!(rate.price, Decimal::ONE);
+        assert!(rate.path.is_empty());
+    }
+
+    #[test]
+    fn inverted_listing() {
+        let tickers = [ticker("kraken", "ETH-BTC", "0.05", 0)];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        let rate = rates.rate("BTC", "ETH", 3).unwrap();
+
+        assert_eq!(rate.price, decimal("20"));
+        assert_eq!(route(&rate), vec!["kraken:BTC>ETH"]);
+        assert!(rate.path[0].inverted);
+    }
+
+    #[test]
+    fn multi_hop_path() {
+        let tickers = [
+            ticker("kraken", "ETH-BTC", "0.05", 200),
+            ticker("binance", "BTC-USDT", "30000", 100),
+        ];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        let rate = rates.rate("ETH", "USDT", 3).unwrap();
+        assert_eq!(rate.price, decimal("1500"));
+        assert_eq!(route(&rate), vec!["kraken:ETH>BTC", "binance:BTC>USDT"]);
+        assert_eq!(rate.staleness_ms, 200);
+
+        let rate = rates.rate("USDT", "ETH", 3).unwrap();
+        assert_eq!(rate.price.round_dp(10), decimal("0.0006666667"));
+        assert_eq!(route(&rate), vec!["binance:USDT>BTC", "kraken:BTC>ETH"]);
+        assert!(rate.path.iter().all(|hop| hop.inverted));
+    }
+
+    #[test]
+    fn max_hops_caps_the_path() {
+        let tickers = [
+            ticker("okx", "SOL-ETH", "0.02", 0),
+            ticker("kraken", "ETH-BTC", "0.05", 0),
+            ticker("binance", "BTC-USDT", "30000", 0),
+        ];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        assert!(rates.rate("SOL", "USDT", 2).is_none());
+        assert_eq!(rates.rate("SOL", "USDT", 3).unwrap().price, decimal("30"));
+        assert!(rates.rate("SOL", "BTC", 1).is_none());
+    }
+
+    #[test]
+    fn fewest_hops_then_freshest_path() {
+        let tickers = [
+            ticker("binance", "ETH-USDT", "1500", 50_000),
+            ticker("kraken", "ETH-BTC", "0.05", 10),
+            ticker("binance", "BTC-USDC", "30000", 10),
+            ticker("coinbase", "ETH-EUR", "1400", 20_000),
+            ticker("kraken", "EUR-USDC", "1.07", 10),
+        ];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        // One stale hop beats two fresh ones
+        let rate = rates.rate("ETH", "USDT", 3).unwrap();
+        assert_eq!(route(&rate), vec!["binance:ETH>USDT"]);
+        assert_eq!(rate.staleness_ms, 50_000);
+
+        // Both paths take two hops, the oldest ticker of the one through BTC
+        // is fresher
+        let rate = rates.rate("ETH", "USDC", 3).unwrap();
+        assert_eq!(route(&rate), vec!["kraken:ETH>BTC", "binance:BTC>USDC"]);
+        assert_eq!(rate.staleness_ms, 10);
+    }
+
+    #[test]
+    fn stale_and_undated_tickers_are_left_out() {
+        let mut undated = ticker("okx", "BTC-USDT", "30000", 0);
+        undated.received_time = None;
+        let tickers = [
+            ticker("binance", "BTC-USDT", "30000", MAX_AGE_MS + 1),
+            undated,
+            ticker("kraken", "ETH-BTC", "0.05", MAX_AGE_MS),
+        ];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        assert!(rates.rate("BTC", "USDT", 3).is_none());
+        assert_eq!(
+            rates.rate("ETH", "BTC", 3).unwrap().staleness_ms,
+            MAX_AGE_MS
+        );
+    }
+
+    #[test]
+    fn composite_tickers_are_left_out() {
+        let tickers = [ticker(COMPOSITE_SOURCE, "BTC-USDT", "30000", 0)];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        assert!(rates.rate("BTC", "USDT", 3).is_none());
+    }
+
+    #[test]
+    fn most_liquid_listing_wins() {
+        let tickers = [
+            with_volume(ticker("binance", "BTC-USDT", "30000", 0), "10"),
+            with_volume(ticker("okx", "BTC-USDT", "30010", 5_000), "250.5"),
+            ticker("bybit", "BTC-USDT", "29990", 0),
+        ];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        let rate = rates.rate("BTC", "USDT", 3).unwrap();
+        assert_eq!(route(&rate), vec!["okx:BTC>USDT"]);
+
+        let rate = rates.rate("USDT", "BTC", 3).unwrap();
+        assert_eq!(route(&rate), vec!["okx:USDT>BTC"]);
+    }
+
+    #[test]
+    fn freshest_listing_wins_without_volumes() {
+        let tickers = [
+            ticker("binance", "BTC-USDT", "30000", 3_000),
+            ticker("bybit", "BTC-USDT", "29990", 1_000),
+        ];
+        let rates = CrossRates::new(&tickers, NOW, MAX_AGE_MS);
+
+        let rate = rates.rate("BTC", "USDT", 3).unwrap();
+        assert_eq!(route(&rate), vec!["bybit:BTC>USDT"]);
+    }
+}
@@ -0,0 +1,83 @@
+use chrono::Utc;
+use log::warn;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+/// Frame a connector failed to decode
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub feed: String,
+    /// Receive time in milliseconds since the epoch
+    pub time: i64,
+    pub error: String,
+    pub payload: String,
+}
+
+#[derive(Default)]
+struct DeadLetters {
+    letters: VecDeque<DeadLetter>,
+    // Failures per feed since startup, including the evicted ones
+    counts: BTreeMap<String, u64>,
+}
+
+/// Bounded buffer of the latest frames which failed to decode, the oldest
+/// are dropped once `capacity` is reached
+#[derive(Clone)]
+pub struct DeadLetterStore {
+    dead_letters: Arc<Mutex<DeadLetters>>,
+    capacity: usize,
+}
+
+impl DeadLetterStore {
+    pub fn new(capacity: usize) -> Self {
+        DeadLetterStore {
+            dead_letters: Arc::new(Mutex::new(DeadLetters::default())),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, feed: &str, payload: &str, error: &eyre::Report) {
+        warn!("Failed to decode {} frame: {}\n{}", feed, error, payload);
+
+        let mut dead_letters = self.dead_letters.lock().unwrap();
+        *dead_letters.counts.entry(feed.to_string()).or_default() += 1;
+
+        if self.capacity == 0 {
+            return;
+        }
+        if dead_letters.letters.len() >= self.capacity {
+            dead_letters.letters.pop_front();
+        }
+        dead_letters.letters.push_back(DeadLetter {
+            feed: feed.to_string(),
+            time: Utc::now().timestamp_millis(),
+            error: format!("{:#}", error),
+            payload: payload.to_string(),
+        });
+    }
+
+    /// Buffered frames of a feed (or all feeds), newest first
+    pub fn letters(&self, feed: Option<&str>) -> Vec<DeadLetter> {
+        self.dead_letters
+            .lock()
+            .unwrap()
+            .letters
+            .iter()
+            .rev()
+            .filter(|letter| feed.map_or(true, |feed| letter.feed == feed))
+            .cloned()
+            .collect()
+    }
+
+    pub fn counts(&self) -> BTreeMap<String, u64> {
+        self.dead_letters.lock().unwrap().counts.clone()
+    }
+
+    /// Empties the buffer, counts are kept
+    pub fn clear(&self) {
+        self.dead_letters.lock().unwrap().letters.clear();
+    }
+}
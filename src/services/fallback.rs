@@ -0,0 +1,111 @@
+use chrono::Utc;
+use eyre::Result;
+use log::{info, warn};
+use redis::{Client as RedisClient, Connection};
+use std::sync::Arc;
+use tokio::{
+    sync::watch,
+    time::{interval, Instant, MissedTickBehavior},
+};
+
+use crate::services::{
+    connector::{publish, ExchangeConnector},
+    supervisor::FeedHandle,
+};
+use crate::AppContext;
+
+/// Polls the tickers of a feed over REST while its WebSocket has been down
+/// for `fallback_after_secs`, and stops once it streams again. Polled tickers
+/// go through the same pipeline, marked as `degraded`.
+pub async fn run_fallback(
+    app_context: AppContext,
+    connector: Arc<dyn ExchangeConnector>,
+    symbols: watch::Receiver<Vec<String>>,
+    feed: FeedHandle,
+) {
+    let poll_interval = match connector.poll_interval() {
+        Some(poll_interval) => poll_interval,
+        None => return,
+    };
+    let fallback_after_ms = app_context.config.fallback_after_secs as i64 * 1000;
+
+    let mut redis_connection = match RedisClient::open(app_context.config.redis_url.as_str())
+        .and_then(|client| client.get_connection())
+    {
+        Ok(redis_connection) => redis_connection,
+        Err(err) => {
+            warn!("No REST fallback for {}: {}", connector.name(), err);
+            return;
+        }
+    };
+
+    let mut timer = interval(poll_interval);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        timer.tick().await;
+
+        let status = match feed.status() {
+            Some(status) => status,
+            None => return,
+        };
+        let down_for = status
+            .down_since
+            .map(|down_since| Utc::now().timestamp_millis() - down_since);
+
+        match down_for {
+            Some(down_for) if down_for >= fallback_after_ms => {
+                if !status.fallback {
+                    warn!(
+                        "{} down for {} seconds, polling tickers over REST",
+                        status.feed,
+                        down_for / 1000
+                    );
+                    feed.set_fallback(true);
+                }
+            }
+            _ => {
+                if status.fallback {
+                    info!("{} is live again, REST polling stopped", status.feed);
+                    feed.set_fallback(false);
+                }
+                continue;
+            }
+        }
+
+        let subscribed = symbols.borrow().clone();
+        if let Err(err) = poll(
+            &app_context,
+            &mut redis_connection,
+            connector.as_ref(),
+            &subscribed,
+        )
+        .await
+        {
+            warn!("Failed to poll {} tickers: {:#}", connector.name(), err);
+        }
+    }
+}
+
+async fn poll(
+    app_context: &AppContext,
+    redis_connection: &mut Connection,
+    connector: &dyn ExchangeConnector,
+    symbols: &[String],
+) -> Result<()> {
+    if symbols.is_empty() {
+        return Ok(());
+    }
+
+    let received_at = Instant::now();
+    let received_time = Utc::now().timestamp_millis();
+
+    for mut ws_message in connector.poll_tickers(symbols).await? {
+        ws_message.received_time = Some(received_time);
+        ws_message.degraded = true;
+
+        publish(app_context, redis_connection, ws_message, received_at)?;
+    }
+
+    Ok(())
+}
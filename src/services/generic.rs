@@ -0,0 +1,377 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use eyre::{bail, eyre, Result, WrapErr};
+use log::warn;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashSet, str::FromStr};
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::services::{
+    composite::COMPOSITE_SOURCE,
+    connector::{ExchangeConnector, FeedEvent},
+    symbols::{SymbolInfo, SymbolRegistry},
+    ws_message::WsMessage,
+};
+
+/// Sources of the built-in connectors and of the composite tickers. A venue
+/// named like one of them would mix its tickers and markets with theirs.
+const RESERVED_NAMES: [&str; 6] = [
+    "binance",
+    "coinbase",
+    "kraken",
+    "okx",
+    "bybit",
+    COMPOSITE_SOURCE,
+];
+
+/// Venue declared in `VENUES_FILE`, for venues whose ticker feed only needs
+/// a subscribe message and a few JSON fields
+#[derive(Deserialize, Debug, Clone)]
+pub struct VenueSpec {
+    /// Used in logs, and lowercased as the ticker source
+    pub name: String,
+    pub ws_url: String,
+    pub markets: Vec<MarketSpec>,
+    /// Subscribe message template. A string `"{symbols}"` is replaced with
+    /// the list of venue symbols and everything is sent in one message,
+    /// otherwise one message is sent per symbol with `{symbol}` replaced
+    /// inside strings.
+    pub subscribe: Value,
+    /// Conditions a frame has to meet to be a ticker
+    #[serde(default)]
+    pub filters: Vec<FilterSpec>,
+    /// Path to an array of tickers, for venues batching them in one frame
+    pub items: Option<String>,
+    pub fields: FieldSpec,
+    pub ping: Option<Value>,
+    pub ping_interval_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MarketSpec {
+    /// Symbol as used on the venue feed
+    pub symbol: String,
+    pub base: String,
+    pub quote: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FilterSpec {
+    pub path: String,
+    pub equals: Value,
+}
+
+/// Dot separated paths (`data.0.price`) of the ticker fields, relative to
+/// the items when `items` is set
+#[derive(Deserialize, Debug, Clone)]
+pub struct FieldSpec {
+    pub symbol: String,
+    /// Stripped from the symbol, e.g. `live_trades_` for Bitstamp channels
+    #[serde(default)]
+    pub symbol_prefix: String,
+    pub price: String,
+    pub time: Option<String>,
+    #[serde(default)]
+    pub time_unit: TimeUnit,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    pub volume: Option<String>,
+}
+
+/// Unit of numeric event times, RFC 3339 strings are detected
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    Seconds,
+    #[default]
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+/// Venues of `path`, which have to be named apart from each other and from
+/// the built-in sources
+pub fn load_venues(path: &str) -> Result<Vec<VenueSpec>> {
+    let venues = std::fs::read_to_string(path).wrap_err_with(|| format!("Reading {}", path))?;
+    let venues: Vec<VenueSpec> =
+        serde_json::from_str(&venues).wrap_err_with(|| format!("Parsing {}", path))?;
+
+    let mut names = HashSet::new();
+    for venue in &venues {
+        let source = venue.name.to_lowercase();
+
+        if source.is_empty() {
+            bail!("Venue without a name in {}", path);
+        }
+        if RESERVED_NAMES.contains(&source.as_str()) {
+            bail!("Venue name {} in {} is reserved", venue.name, path);
+        }
+        if !names.insert(source) {
+            bail!("Venue {} is declared twice in {}", venue.name, path);
+        }
+    }
+
+    Ok(venues)
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| match value {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => value.get(key),
+        })
+}
+
+/// Strings as they are, numbers in their textual form
+fn lookup_string(value: &Value, path: &str) -> Option<String> {
+    match lookup(value, path)? {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+fn lookup_time(value: &Value, path: &str, unit: TimeUnit) -> Option<i64> {
+    let time = match lookup(value, path)? {
+        Value::Number(number) => number.as_f64()?,
+        Value::String(text) => match text.parse::<f64>() {
+            Ok(time) => time,
+            Err(_) => return Some(DateTime::parse_from_rfc3339(text).ok()?.timestamp_millis()),
+        },
+        _ => return None,
+    };
+
+    let millis = match unit {
+        TimeUnit::Seconds => time * 1000.0,
+        TimeUnit::Milliseconds => time,
+        TimeUnit::Microseconds => time / 1000.0,
+        TimeUnit::Nanoseconds => time / 1_000_000.0,
+    };
+
+    Some(millis as i64)
+}
+
+/// Replaces `{symbol}` in every string of the template
+fn fill_template(template: &Value, symbol: &str) -> Value {
+    match template {
+        Value::String(text) => Value::String(text.replace("{symbol}", symbol)),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| fill_template(item, symbol))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), fill_template(value, symbol)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// Replaces the `"{symbols}"` string of the template with the symbol list,
+/// `None` when the template has no such string
+fn fill_symbols(template: &Value, symbols: &[String]) -> Option<Value> {
+    match template {
+        Value::String(text) if text == "{symbols}" => Some(Value::from(symbols.to_vec())),
+        Value::Array(items) => {
+            let filled = items
+                .iter()
+                .map(|item| fill_symbols(item, symbols))
+                .collect::<Vec<_>>();
+            if filled.iter().all(Option::is_none) {
+                return None;
+            }
+
+            Some(Value::Array(
+                filled
+                    .into_iter()
+                    .zip(items)
+                    .map(|(filled, item)| filled.unwrap_or_else(|| item.clone()))
+                    .collect(),
+            ))
+        }
+        Value::Object(fields) => {
+            let mut found = false;
+            let filled = fields
+                .iter()
+                .map(|(key, value)| {
+                    let value = match fill_symbols(value, symbols) {
+                        Some(filled) => {
+                            found = true;
+                            filled
+                        }
+                        None => value.clone(),
+                    };
+                    (key.clone(), value)
+                })
+                .collect();
+
+            found.then_some(Value::Object(filled))
+        }
+        _ => None,
+    }
+}
+
+pub struct GenericConnector {
+    // Leaked once per configured venue, connectors live as long as the server
+    name: &'static str,
+    source: String,
+    spec: VenueSpec,
+    symbols: SymbolRegistry,
+}
+
+impl GenericConnector {
+    pub fn new(spec: VenueSpec, symbols: &SymbolRegistry) -> Self {
+        GenericConnector {
+            name: Box::leak(spec.name.clone().into_boxed_str()),
+            source: spec.name.to_lowercase(),
+            spec,
+            symbols: symbols.clone(),
+        }
+    }
+
+    fn decode_ticker(&self, item: &Value) -> Result<Option<WsMessage>> {
+        let fields = &self.spec.fields;
+
+        let symbol = lookup_string(item, &fields.symbol)
+            .ok_or_else(|| eyre!("Missing symbol at {}", fields.symbol))?;
+        let symbol = symbol
+            .strip_prefix(fields.symbol_prefix.as_str())
+            .unwrap_or(&symbol);
+        let price = lookup_string(item, &fields.price)
+            .ok_or_else(|| eyre!("Missing price at {}", fields.price))?;
+        // Numbers may come in exponent notation
+        let price = Decimal::from_str(&price)
+            .or_else(|_| Decimal::from_scientific(&price))
+            .wrap_err_with(|| format!("Invalid price {}", price))?;
+
+        let info = match self.symbols.get(&self.source, symbol) {
+            Some(info) => info,
+            None => {
+                warn!("Unknown {} symbol: {}", self.name, symbol);
+                return Ok(None);
+            }
+        };
+
+        let mut ws_message = WsMessage::new(&info.source, &info.base, &info.quote, price);
+        ws_message.best_bid = fields
+            .best_bid
+            .as_ref()
+            .and_then(|path| lookup_string(item, path));
+        ws_message.best_ask = fields
+            .best_ask
+            .as_ref()
+            .and_then(|path| lookup_string(item, path));
+        ws_message.volume = fields
+            .volume
+            .as_ref()
+            .and_then(|path| lookup_string(item, path));
+        ws_message.event_time = fields
+            .time
+            .as_ref()
+            .and_then(|path| lookup_time(item, path, fields.time_unit));
+
+        Ok(Some(ws_message))
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for GenericConnector {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.spec.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        let messages = match fill_symbols(&self.spec.subscribe, symbols) {
+            Some(message) => vec![message],
+            None => symbols
+                .iter()
+                .map(|symbol| fill_template(&self.spec.subscribe, symbol))
+                .collect(),
+        };
+
+        Ok(messages
+            .into_iter()
+            .map(|message| Message::Text(message.to_string()))
+            .collect())
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<Value>(data)?;
+
+        // Skip everything which isn't a ticker (acks, heartbeats, etc.)
+        let is_ticker = self
+            .spec
+            .filters
+            .iter()
+            .all(|filter| lookup(&v, &filter.path) == Some(&filter.equals));
+        if !is_ticker {
+            return Ok(vec![]);
+        }
+
+        let items = match &self.spec.items {
+            Some(path) => match lookup(&v, path) {
+                Some(Value::Array(items)) => items.iter().collect(),
+                _ => bail!("Missing ticker list at {}", path),
+            },
+            None => vec![&v],
+        };
+
+        let mut events = vec![];
+        for item in items {
+            if let Some(ws_message) = self.decode_ticker(item)? {
+                events.push(FeedEvent::Ticker(ws_message));
+            }
+        }
+
+        Ok(events)
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        let infos = self
+            .spec
+            .markets
+            .iter()
+            .map(|market| {
+                SymbolInfo::new(
+                    &self.source,
+                    &market.symbol,
+                    &market.base,
+                    &market.quote,
+                    "online",
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let symbols = infos
+            .iter()
+            .filter(|info| self.symbols.allows(info))
+            .map(|info| info.venue_symbol.clone())
+            .collect();
+        self.symbols.replace(&self.source, infos);
+
+        Ok(symbols)
+    }
+
+    fn ping_interval(&self) -> Option<Duration> {
+        self.spec.ping_interval_secs.map(Duration::from_secs)
+    }
+
+    fn ping_message(&self) -> Message {
+        match &self.spec.ping {
+            Some(ping) => Message::Text(ping.to_string()),
+            None => Message::Ping(vec![]),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use reqwest::Client as ReqwestClient;
+use std::sync::OnceLock;
+use tokio::time::Duration;
+
+/// Upper bound for a whole REST request, so a venue which accepts the
+/// connection and never answers fails like any other request error instead of
+/// stalling the feed waiting on it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP client shared by the venue connectors, reusing its connection pool
+/// across requests
+pub fn client() -> &'static ReqwestClient {
+    static CLIENT: OnceLock<ReqwestClient> = OnceLock::new();
+
+    CLIENT.get_or_init(|| {
+        ReqwestClient::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("HTTP client without custom TLS or proxy settings")
+    })
+}
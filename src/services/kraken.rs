@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use eyre::{bail, Result};
+use log::warn;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, fmt};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::config::Config;
+use crate::services::{
+    connector::{ExchangeConnector, FeedEvent},
+    http,
+    symbols::{SymbolInfo, SymbolRegistry},
+    ws_message::WsMessage,
+};
+
+#[derive(Deserialize, Debug)]
+struct KrakenMessage {
+    #[serde(default)]
+    data: Vec<KrakenTicker>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct KrakenTicker {
+    pub symbol: String,
+    // Parsed from the float's shortest form, Kraken sends prices as numbers
+    pub last: Decimal,
+    pub high: Option<serde_json::Number>,
+    pub low: Option<serde_json::Number>,
+    pub volume: Option<serde_json::Number>,
+    pub bid: Option<serde_json::Number>,
+    pub ask: Option<serde_json::Number>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AssetPairsResponse {
+    error: Vec<String>,
+    #[serde(default)]
+    result: HashMap<String, AssetPair>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AssetPair {
+    wsname: Option<String>,
+    status: Option<String>,
+    tick_size: Option<String>,
+    costmin: Option<String>,
+}
+
+impl fmt::Display for KrakenTicker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(Ticker: {} - {})", self.symbol, self.last)
+    }
+}
+
+/// Kraken REST API still uses legacy asset codes (e.g. `XBT/USD`), while the
+/// v2 WebSocket API expects the common ones (`BTC/USD`). The legacy codes are
+/// `kraken` aliases of the currencies.
+async fn fetch_asset_pairs(api_url: &str, symbols: &SymbolRegistry) -> Result<Vec<SymbolInfo>> {
+    let url = format!("{}/0/public/AssetPairs", api_url);
+
+    let response = http::client().get(url).send().await?;
+
+    if response.status().is_success() {
+        let body = response.text().await?;
+        let asset_pairs: AssetPairsResponse = serde_json::from_str(&body)?;
+
+        if !asset_pairs.error.is_empty() {
+            bail!(asset_pairs.error.join(", "))
+        }
+
+        let symbols = asset_pairs
+            .result
+            .into_values()
+            .filter_map(|pair| {
+                let (base, quote) = pair.wsname.as_deref()?.split_once('/')?;
+                let base = symbols.canonical("kraken", base);
+                let quote = symbols.canonical("kraken", quote);
+                let symbol = format!("{}/{}", base, quote);
+                let status = pair.status.as_deref().unwrap_or("online");
+
+                let mut info = SymbolInfo::new("kraken", &symbol, &base, &quote, status);
+                info.tick_size = pair.tick_size;
+                info.min_notional = pair.costmin;
+
+                Some(info)
+            })
+            .collect();
+
+        Ok(symbols)
+    } else if let Ok(error_body) = response.text().await {
+        bail!(error_body)
+    } else {
+        bail!("Unknown error")
+    }
+}
+
+pub struct KrakenConnector {
+    ws_url: String,
+    api_url: String,
+    symbols: SymbolRegistry,
+}
+
+impl KrakenConnector {
+    pub fn new(config: &Config, symbols: &SymbolRegistry) -> Self {
+        KrakenConnector {
+            ws_url: config.kraken_ws_url.clone(),
+            api_url: config.kraken_api_url.clone(),
+            symbols: symbols.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for KrakenConnector {
+    fn name(&self) -> &'static str {
+        "Kraken"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        let subscribe_msg = json!({
+            "method": "subscribe",
+            "params": { "channel": "ticker", "symbol": symbols }
+        });
+
+        Ok(vec![Message::Text(serde_json::to_string(&subscribe_msg)?)])
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        // Skip subscription acks, heartbeats and status messages
+        if v["channel"] != "ticker" {
+            return Ok(vec![]);
+        }
+
+        let kraken_message: KrakenMessage = serde_json::from_value(v)?;
+
+        Ok(kraken_message
+            .data
+            .into_iter()
+            .filter_map(|ticker| match self.symbols.get("kraken", &ticker.symbol) {
+                Some(info) => Some(FeedEvent::Ticker(WsMessage::from_kraken(&info, ticker))),
+                None => {
+                    warn!("Unknown Kraken symbol: {}", ticker.symbol);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        let infos = fetch_asset_pairs(&self.api_url, &self.symbols).await?;
+
+        let mut symbols = infos
+            .iter()
+            .filter(|info| info.status == "online" && self.symbols.allows(info))
+            .map(|info| info.venue_symbol.clone())
+            .collect::<Vec<_>>();
+        symbols.sort();
+        self.symbols.replace("kraken", infos);
+
+        Ok(symbols)
+    }
+}
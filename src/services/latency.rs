@@ -0,0 +1,130 @@
+use async_graphql::{Enum, SimpleObject};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Upper bounds of the histogram buckets in milliseconds, slower samples go
+/// to an overflow bucket
+const BUCKET_BOUNDS_MS: [f64; 14] = [
+    0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 10000.0,
+];
+
+#[derive(Enum, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyStage {
+    /// Venue event time to the frame arriving at the server, includes clock skew
+    ExchangeToReceive,
+    /// Frame arriving to the ticker being sent to the websocket clients
+    ReceiveToPublish,
+}
+
+#[derive(Default)]
+struct Histogram {
+    counts: [u64; BUCKET_BOUNDS_MS.len() + 1],
+    count: u64,
+    sum_ms: f64,
+    max_ms: f64,
+}
+
+impl Histogram {
+    fn record(&mut self, ms: f64) {
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    /// Upper bound of the bucket holding the `quantile`, the max for the
+    /// overflow bucket
+    fn quantile(&self, quantile: f64) -> f64 {
+        let rank = (quantile * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank.max(1) {
+                return BUCKET_BOUNDS_MS
+                    .get(bucket)
+                    .copied()
+                    .unwrap_or(self.max_ms)
+                    .min(self.max_ms);
+            }
+        }
+
+        self.max_ms
+    }
+}
+
+#[derive(Serialize, SimpleObject, Debug, Clone)]
+pub struct LatencyBucket {
+    /// Upper bound in milliseconds, empty for the overflow bucket
+    pub le_ms: Option<f64>,
+    pub count: u64,
+}
+
+#[derive(Serialize, SimpleObject, Debug, Clone)]
+pub struct LatencyStats {
+    pub source: String,
+    pub stage: LatencyStage,
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+/// Latency histograms per source and pipeline stage, since startup. Only
+/// streamed tickers are measured, polled and composite ones are left out.
+#[derive(Clone, Default)]
+pub struct LatencyTracker {
+    histograms: Arc<Mutex<BTreeMap<(String, LatencyStage), Histogram>>>,
+}
+
+impl LatencyTracker {
+    pub fn record(&self, source: &str, stage: LatencyStage, ms: f64) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry((source.to_string(), stage))
+            .or_default()
+            // Venue clocks running ahead of ours would give negative values
+            .record(ms.max(0.0));
+    }
+
+    pub fn stats(&self, source: Option<&str>) -> Vec<LatencyStats> {
+        self.histograms
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((histogram_source, _), _)| {
+                source.map_or(true, |source| source == histogram_source)
+            })
+            .map(|((source, stage), histogram)| LatencyStats {
+                source: source.clone(),
+                stage: *stage,
+                count: histogram.count,
+                mean_ms: histogram.sum_ms / histogram.count.max(1) as f64,
+                p50_ms: histogram.quantile(0.5),
+                p99_ms: histogram.quantile(0.99),
+                max_ms: histogram.max_ms,
+                buckets: histogram
+                    .counts
+                    .iter()
+                    .enumerate()
+                    .map(|(bucket, count)| LatencyBucket {
+                        le_ms: BUCKET_BOUNDS_MS.get(bucket).copied(),
+                        count: *count,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
@@ -1,5 +1,26 @@
+pub mod assets;
 pub mod binance;
+pub mod bybit;
+pub mod candles;
 pub mod coinbase;
-pub mod redis_connection;
+pub mod composite;
+pub mod connector;
+pub mod cross_rates;
+pub mod dead_letters;
+pub mod fallback;
+pub mod generic;
+pub mod http;
+pub mod kraken;
+pub mod latency;
+pub mod okx;
+pub mod order_book;
+pub mod price_history;
+pub mod reconnect;
+pub mod recorder;
+pub mod replay;
+pub mod supervisor;
+pub mod symbols;
+pub mod ticker_store;
+pub mod trade;
 pub mod websocket;
 pub mod ws_message;
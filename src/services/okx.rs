@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+use eyre::{bail, Result};
+use log::warn;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt;
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::config::Config;
+use crate::services::{
+    connector::{ExchangeConnector, FeedEvent},
+    http,
+    symbols::{SymbolInfo, SymbolRegistry},
+    ws_message::WsMessage,
+};
+
+#[derive(Deserialize, Debug)]
+struct OkxMessage {
+    #[serde(default)]
+    data: Vec<OkxTicker>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxTicker {
+    pub inst_id: String,
+    pub last: Decimal,
+    pub open_24h: Option<String>,
+    pub high_24h: Option<String>,
+    pub low_24h: Option<String>,
+    pub vol_24h: Option<String>,
+    pub vol_ccy_24h: Option<String>,
+    pub bid_px: Option<String>,
+    pub ask_px: Option<String>,
+    pub ts: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InstrumentsResponse {
+    code: String,
+    #[serde(default)]
+    msg: String,
+    #[serde(default)]
+    data: Vec<Instrument>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Instrument {
+    inst_id: String,
+    base_ccy: String,
+    quote_ccy: String,
+    state: String,
+    tick_sz: Option<String>,
+    lot_sz: Option<String>,
+}
+
+impl fmt::Display for OkxTicker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(Ticker: {} - {})", self.inst_id, self.last)
+    }
+}
+
+async fn fetch_instruments(api_url: &str) -> Result<Vec<SymbolInfo>> {
+    let url = format!("{}/api/v5/public/instruments?instType=SPOT", api_url);
+
+    let response = http::client().get(url).send().await?;
+
+    if response.status().is_success() {
+        let body = response.text().await?;
+        let instruments: InstrumentsResponse = serde_json::from_str(&body)?;
+
+        if instruments.code != "0" {
+            bail!(instruments.msg)
+        }
+
+        let symbols = instruments
+            .data
+            .into_iter()
+            .map(|instrument| {
+                let mut info = SymbolInfo::new(
+                    "okx",
+                    &instrument.inst_id,
+                    &instrument.base_ccy,
+                    &instrument.quote_ccy,
+                    &instrument.state,
+                );
+                info.tick_size = instrument.tick_sz;
+                info.step_size = instrument.lot_sz;
+
+                info
+            })
+            .collect();
+
+        Ok(symbols)
+    } else if let Ok(error_body) = response.text().await {
+        bail!(error_body)
+    } else {
+        bail!("Unknown error")
+    }
+}
+
+pub struct OkxConnector {
+    ws_url: String,
+    api_url: String,
+    symbols: SymbolRegistry,
+}
+
+impl OkxConnector {
+    pub fn new(config: &Config, symbols: &SymbolRegistry) -> Self {
+        OkxConnector {
+            ws_url: config.okx_ws_url.clone(),
+            api_url: config.okx_api_url.clone(),
+            symbols: symbols.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for OkxConnector {
+    fn name(&self) -> &'static str {
+        "OKX"
+    }
+
+    fn url(&self, _symbols: &[String]) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self, symbols: &[String]) -> Result<Vec<Message>> {
+        let args = symbols
+            .iter()
+            .map(|symbol| json!({ "channel": "tickers", "instId": symbol }))
+            .collect::<Vec<_>>();
+        let subscribe_msg = json!({ "op": "subscribe", "args": args });
+
+        Ok(vec![Message::Text(serde_json::to_string(&subscribe_msg)?)])
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        // Reply to our keepalive ping
+        if data == "pong" {
+            return Ok(vec![]);
+        }
+
+        let v = serde_json::from_str::<serde_json::Value>(data)?;
+
+        // Subscription acks and errors come with an `event` instead of `data`
+        if v["arg"]["channel"] != "tickers" || v.get("data").is_none() {
+            return Ok(vec![]);
+        }
+
+        let okx_message: OkxMessage = serde_json::from_value(v)?;
+
+        Ok(okx_message
+            .data
+            .into_iter()
+            .filter_map(|ticker| match self.symbols.get("okx", &ticker.inst_id) {
+                Some(info) => Some(FeedEvent::Ticker(WsMessage::from_okx(&info, ticker))),
+                None => {
+                    warn!("Unknown OKX instrument: {}", ticker.inst_id);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        let infos = fetch_instruments(&self.api_url).await?;
+
+        let symbols = infos
+            .iter()
+            .filter(|info| info.status == "live" && self.symbols.allows(info))
+            .map(|info| info.venue_symbol.clone())
+            .collect();
+        self.symbols.replace("okx", infos);
+
+        Ok(symbols)
+    }
+
+    // OKX closes connections which have been idle for 30 seconds
+    fn ping_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(20))
+    }
+
+    fn ping_message(&self) -> Message {
+        Message::Text("ping".to_string())
+    }
+}
@@ -0,0 +1,400 @@
+use async_graphql::SimpleObject;
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, RwLock},
+};
+use tokio::time::{Duration, Instant};
+
+/// Sequenced diffs kept while a book waits for its snapshot, 100 seconds of a
+/// 100ms depth stream
+const MAX_BUFFERED_DIFFS: usize = 1000;
+
+/// Order book change decoded from a venue depth feed. Prices and sizes are
+/// kept as sent by the venue, a size of zero removes the level.
+#[derive(Debug, Clone)]
+pub struct BookUpdate {
+    pub source: String,
+    /// Symbol in `BASE-QUOTE` form
+    pub symbol: String,
+    pub kind: BookUpdateKind,
+    pub bids: Vec<(String, String)>,
+    pub asks: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BookUpdateKind {
+    /// Full book, replaces whatever was there
+    Snapshot { sequence: Option<u64> },
+    /// Incremental update covering sequence numbers `first..=last`, venues
+    /// without sequence numbers send `None`
+    Diff {
+        first: Option<u64>,
+        last: Option<u64>,
+    },
+}
+
+/// Outcome of applying a `BookUpdate`
+#[derive(Debug, PartialEq)]
+pub enum BookStatus {
+    Updated,
+    /// Update is older than the book, nothing changed
+    Skipped,
+    /// Book has no snapshot, a sequence gap was detected or the snapshot is
+    /// older than the buffered diffs, a new snapshot is needed before diffs
+    /// can be applied
+    OutOfSync,
+}
+
+/// Price used as an order book key, ordered numerically
+#[derive(Debug, Clone)]
+struct PriceKey(f64);
+
+impl PartialEq for PriceKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PriceKey {}
+
+impl PartialOrd for PriceKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PriceKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct PriceLevel {
+    pub price: String,
+    pub size: String,
+}
+
+#[derive(Debug, Default)]
+struct OrderBook {
+    bids: BTreeMap<PriceKey, PriceLevel>,
+    asks: BTreeMap<PriceKey, PriceLevel>,
+    sequence: Option<u64>,
+    synced: bool,
+    /// Sequenced diffs received while out of sync, applied on top of the
+    /// next snapshot
+    buffered: VecDeque<BookUpdate>,
+    snapshot_requested: Option<Instant>,
+}
+
+impl OrderBook {
+    /// Applies a diff to a synced book
+    fn apply_diff(&mut self, update: BookUpdate) -> BookStatus {
+        let (first, last) = match update.kind {
+            BookUpdateKind::Diff { first, last } => (first, last),
+            BookUpdateKind::Snapshot { .. } => return BookStatus::Skipped,
+        };
+
+        if let (Some(sequence), Some(first), Some(last)) = (self.sequence, first, last) {
+            if last <= sequence {
+                return BookStatus::Skipped;
+            }
+            if first > sequence + 1 {
+                self.synced = false;
+                self.buffer(update);
+                return BookStatus::OutOfSync;
+            }
+        }
+
+        self.apply_levels(update.bids, update.asks);
+        self.sequence = last.or(self.sequence);
+
+        BookStatus::Updated
+    }
+
+    /// Keeps a diff for the next snapshot. Diffs without sequence numbers
+    /// can't be matched against a snapshot, so they are dropped.
+    fn buffer(&mut self, update: BookUpdate) {
+        if !matches!(update.kind, BookUpdateKind::Diff { last: Some(_), .. }) {
+            return;
+        }
+
+        if self.buffered.len() >= MAX_BUFFERED_DIFFS {
+            self.buffered.pop_front();
+        }
+        self.buffered.push_back(update);
+    }
+
+    fn apply_levels(&mut self, bids: Vec<(String, String)>, asks: Vec<(String, String)>) {
+        for (side, levels) in [(&mut self.bids, bids), (&mut self.asks, asks)] {
+            for (price, size) in levels {
+                let key = match price.parse::<f64>() {
+                    Ok(key) => PriceKey(key),
+                    Err(_) => continue,
+                };
+
+                if size.parse::<f64>().map_or(true, |size| size == 0.0) {
+                    side.remove(&key);
+                } else {
+                    side.insert(key, PriceLevel { price, size });
+                }
+            }
+        }
+    }
+}
+
+/// Top levels of a book, sent to the websocket clients and GraphQL
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct OrderBookView {
+    #[serde(rename = "type")]
+    #[graphql(skip)]
+    pub message_type: &'static str,
+    pub source: String,
+    pub symbol: String,
+    pub sequence: Option<u64>,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+/// In-memory order books keyed by `source-symbol`
+#[derive(Clone, Default)]
+pub struct OrderBookStore {
+    books: Arc<RwLock<HashMap<String, OrderBook>>>,
+}
+
+impl OrderBookStore {
+    pub fn apply(&self, update: BookUpdate) -> BookStatus {
+        let key = format!("{}-{}", update.source, update.symbol);
+        let mut books = self.books.write().unwrap();
+        let book = books.entry(key).or_default();
+
+        match update.kind {
+            BookUpdateKind::Snapshot { sequence } => {
+                let mut buffered = std::mem::take(&mut book.buffered);
+                *book = OrderBook {
+                    sequence,
+                    synced: true,
+                    snapshot_requested: book.snapshot_requested,
+                    ..Default::default()
+                };
+                book.apply_levels(update.bids, update.asks);
+
+                // Diffs which arrived while the snapshot was fetched. Once one
+                // doesn't line up, the snapshot lags behind the stream and the
+                // rest waits for the next one.
+                while let Some(diff) = buffered.pop_front() {
+                    if book.apply_diff(diff) == BookStatus::OutOfSync {
+                        book.buffered.extend(buffered);
+                        return BookStatus::OutOfSync;
+                    }
+                }
+
+                BookStatus::Updated
+            }
+            BookUpdateKind::Diff { .. } => {
+                if !book.synced {
+                    book.buffer(update);
+                    return BookStatus::OutOfSync;
+                }
+
+                book.apply_diff(update)
+            }
+        }
+    }
+
+    /// Whether a snapshot of an out of sync book may be requested, at most
+    /// once per `min_interval`. Counts as requested when it returns true.
+    pub fn request_snapshot(&self, source: &str, symbol: &str, min_interval: Duration) -> bool {
+        let key = format!("{}-{}", source, symbol);
+        let mut books = self.books.write().unwrap();
+        let book = books.entry(key).or_default();
+
+        let due = book
+            .snapshot_requested
+            .map_or(true, |requested| requested.elapsed() >= min_interval);
+        if due {
+            book.snapshot_requested = Some(Instant::now());
+        }
+
+        due
+    }
+
+    /// Forgets the book, e.g. when the feed connection drops
+    pub fn invalidate(&self, source: &str, symbol: &str) {
+        let key = format!("{}-{}", source, symbol);
+
+        if let Some(book) = self.books.write().unwrap().get_mut(&key) {
+            book.synced = false;
+            book.buffered.clear();
+        }
+    }
+
+    pub fn top(&self, source: &str, symbol: &str, depth: usize) -> Option<OrderBookView> {
+        let key = format!("{}-{}", source, symbol);
+        let books = self.books.read().unwrap();
+        let book = books.get(&key).filter(|book| book.synced)?;
+
+        Some(OrderBookView {
+            message_type: "order_book",
+            source: source.to_string(),
+            symbol: symbol.to_string(),
+            sequence: book.sequence,
+            bids: book.bids.values().rev().take(depth).cloned().collect(),
+            asks: book.asks.values().take(depth).cloned().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: &str, size: &str) -> (String, String) {
+        (price.to_string(), size.to_string())
+    }
+
+    fn snapshot(sequence: u64, bids: Vec<(String, String)>) -> BookUpdate {
+        BookUpdate {
+            source: "binance".to_string(),
+            symbol: "BTC-USDT".to_string(),
+            kind: BookUpdateKind::Snapshot {
+                sequence: Some(sequence),
+            },
+            bids,
+            asks: vec![level("101", "1")],
+        }
+    }
+
+    fn diff(first: u64, last: u64, bids: Vec<(String, String)>) -> BookUpdate {
+        BookUpdate {
+            source: "binance".to_string(),
+            symbol: "BTC-USDT".to_string(),
+            kind: BookUpdateKind::Diff {
+                first: Some(first),
+                last: Some(last),
+            },
+            bids,
+            asks: vec![],
+        }
+    }
+
+    fn bids(store: &OrderBookStore) -> Vec<(String, String)> {
+        store
+            .top("binance", "BTC-USDT", 10)
+            .map(|view| {
+                view.bids
+                    .into_iter()
+                    .map(|level| (level.price, level.size))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn diff_before_snapshot_is_buffered() {
+        let store = OrderBookStore::default();
+
+        assert_eq!(
+            store.apply(diff(11, 12, vec![level("99", "2")])),
+            BookStatus::OutOfSync
+        );
+        assert!(store.top("binance", "BTC-USDT", 10).is_none());
+
+        assert_eq!(
+            store.apply(snapshot(10, vec![level("100", "1")])),
+            BookStatus::Updated
+        );
+        assert_eq!(
+            bids(&store),
+            vec![level("100", "1"), level("99", "2")],
+            "buffered diff is replayed on top of the snapshot"
+        );
+    }
+
+    #[test]
+    fn stale_diff_is_skipped() {
+        let store = OrderBookStore::default();
+        store.apply(snapshot(10, vec![level("100", "1")]));
+
+        assert_eq!(
+            store.apply(diff(5, 10, vec![level("100", "7")])),
+            BookStatus::Skipped
+        );
+        assert_eq!(bids(&store), vec![level("100", "1")]);
+    }
+
+    #[test]
+    fn first_diff_may_straddle_the_snapshot() {
+        let store = OrderBookStore::default();
+        store.apply(snapshot(10, vec![level("100", "1")]));
+
+        assert_eq!(
+            store.apply(diff(8, 12, vec![level("100", "3")])),
+            BookStatus::Updated
+        );
+        assert_eq!(
+            store.apply(diff(13, 13, vec![level("98", "1")])),
+            BookStatus::Updated
+        );
+        assert_eq!(bids(&store), vec![level("100", "3"), level("98", "1")]);
+        assert_eq!(
+            store.top("binance", "BTC-USDT", 10).unwrap().sequence,
+            Some(13)
+        );
+    }
+
+    #[test]
+    fn gap_needs_a_new_snapshot() {
+        let store = OrderBookStore::default();
+        store.apply(snapshot(10, vec![level("100", "1")]));
+
+        assert_eq!(
+            store.apply(diff(15, 16, vec![level("99", "1")])),
+            BookStatus::OutOfSync
+        );
+        assert!(store.top("binance", "BTC-USDT", 10).is_none());
+        assert_eq!(
+            store.apply(diff(17, 17, vec![level("98", "1")])),
+            BookStatus::OutOfSync
+        );
+
+        // Snapshot still behind the buffered diffs
+        assert_eq!(
+            store.apply(snapshot(12, vec![level("100", "1")])),
+            BookStatus::OutOfSync
+        );
+
+        assert_eq!(
+            store.apply(snapshot(14, vec![level("100", "2")])),
+            BookStatus::Updated
+        );
+        assert_eq!(
+            bids(&store),
+            vec![level("100", "2"), level("99", "1"), level("98", "1")]
+        );
+    }
+
+    #[test]
+    fn zero_size_removes_the_level() {
+        let store = OrderBookStore::default();
+        store.apply(snapshot(10, vec![level("100", "1"), level("99", "1")]));
+
+        assert_eq!(
+            store.apply(diff(11, 11, vec![level("100", "0.00000000")])),
+            BookStatus::Updated
+        );
+        assert_eq!(bids(&store), vec![level("99", "1")]);
+    }
+
+    #[test]
+    fn snapshot_requests_are_rate_limited() {
+        let store = OrderBookStore::default();
+        let interval = Duration::from_secs(60);
+
+        assert!(store.request_snapshot("binance", "BTC-USDT", interval));
+        assert!(!store.request_snapshot("binance", "BTC-USDT", interval));
+        assert!(store.request_snapshot("binance", "BTC-USDT", Duration::ZERO));
+    }
+}
@@ -0,0 +1,308 @@
+use async_graphql::SimpleObject;
+use chrono::Utc;
+use eyre::{eyre, Result};
+use log::{info, warn};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::{Executor, Sqlite, SqlitePool, Transaction};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    time::{interval, Duration},
+};
+
+use crate::services::{composite::COMPOSITE_SOURCE, ws_message::WsMessage};
+use crate::AppContext;
+
+/// Prices waiting for the writer, newer prices are dropped while it is full
+const PRICE_BUFFER: usize = 10_000;
+
+#[derive(Serialize, Clone, Debug, SimpleObject)]
+pub struct PricePoint {
+    pub source: String,
+    pub symbol: String,
+    pub price: Decimal,
+    /// Publish time in milliseconds since the epoch
+    pub time: i64,
+}
+
+#[derive(Debug)]
+struct PriceInput {
+    source: String,
+    base: String,
+    quote: String,
+    price: Decimal,
+    time: i64,
+}
+
+/// Published venue prices, persisted to `ticker_prices` by `run_price_writer`.
+/// Composite prices are not persisted, they are derived from the venue prices
+/// and are no provider's.
+#[derive(Clone)]
+pub struct PriceHistory {
+    input_tx: mpsc::Sender<PriceInput>,
+    // Prices dropped since the writer last saved
+    dropped: Arc<AtomicU64>,
+}
+
+pub struct PriceReceiver(mpsc::Receiver<PriceInput>);
+
+impl PriceHistory {
+    pub fn new() -> (Self, PriceReceiver) {
+        let (input_tx, input_rx) = mpsc::channel(PRICE_BUFFER);
+
+        let history = PriceHistory {
+            input_tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+
+        (history, PriceReceiver(input_rx))
+    }
+
+    pub fn record(&self, ws_message: &WsMessage) {
+        if ws_message.get_source() == COMPOSITE_SOURCE {
+            return;
+        }
+
+        let input = PriceInput {
+            source: ws_message.get_source().to_string(),
+            base: ws_message.get_base().to_string(),
+            quote: ws_message.get_quote().to_string(),
+            price: ws_message.price,
+            time: ws_message
+                .published_time
+                .unwrap_or_else(|| Utc::now().timestamp_millis()),
+        };
+
+        // A closed channel means the writer is gone, which is logged there
+        if let Err(TrySendError::Full(_)) = self.input_tx.try_send(input) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Saves the recorded prices once a second
+pub async fn run_price_writer(app_context: AppContext, receiver: PriceReceiver) {
+    let PriceReceiver(mut input_rx) = receiver;
+    let dropped = app_context.prices.dropped.clone();
+
+    let mut timer = interval(Duration::from_secs(1));
+    let mut pending = vec![];
+    // `tickers` id per `source-base-quote`
+    let mut ticker_ids = HashMap::new();
+
+    loop {
+        tokio::select! {
+            input = input_rx.recv() => {
+                match input {
+                    Some(input) => pending.push(input),
+                    None => break,
+                }
+            }
+            _ = timer.tick() => {
+                let dropped = dropped.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    warn!("Dropped {} prices, the price writer is behind", dropped);
+                }
+
+                if pending.is_empty() {
+                    continue;
+                }
+
+                let db_connection = &app_context.db_connection;
+                if let Err(err) = save_prices(db_connection, &mut ticker_ids, &pending).await {
+                    warn!("Failed to save prices: {}", err);
+                    // Tickers added by the rolled back transaction are gone
+                    ticker_ids.clear();
+                }
+                pending.clear();
+            }
+        }
+    }
+
+    warn!("Price writer stopped, no more prices");
+}
+
+async fn save_prices(
+    db_connection: &SqlitePool,
+    ticker_ids: &mut HashMap<String, i64>,
+    prices: &[PriceInput],
+) -> Result<()> {
+    let mut transaction = db_connection.begin().await?;
+
+    for input in prices {
+        let key = format!("{}-{}-{}", input.source, input.base, input.quote);
+        let ticker_id = match ticker_ids.get(&key) {
+            Some(ticker_id) => *ticker_id,
+            None => {
+                let ticker_id =
+                    create_ticker(&mut transaction, &input.source, &input.base, &input.quote)
+                        .await?;
+                ticker_ids.insert(key, ticker_id);
+                ticker_id
+            }
+        };
+
+        // Venue prices are far from the 96 bits a decimal can hold
+        let amount = match i64::try_from(input.price.mantissa()) {
+            Ok(amount) => amount,
+            Err(_) => {
+                warn!(
+                    "{} {}-{} price {} too large",
+                    input.source, input.base, input.quote, input.price
+                );
+                continue;
+            }
+        };
+        // DECIMAL(precision, scale) the price fits in, 0.0001 has 1 digit but
+        // needs DECIMAL(4, 4)
+        let scale = input.price.scale() as i64;
+        let precision = (amount.unsigned_abs().to_string().len() as i64).max(scale);
+
+        sqlx::query!(
+            "INSERT INTO ticker_prices
+               (id, ticker_id, amount, precision, scale, created_at, updated_at)
+             SELECT COALESCE(MAX(id), 0) + 1, ?, ?, ?, ?,
+               strftime('%Y-%m-%d %H:%M:%f', ? / 1000.0, 'unixepoch'), CURRENT_TIMESTAMP
+             FROM ticker_prices",
+            ticker_id,
+            amount,
+            precision,
+            scale,
+            input.time,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    info!("Saved {} prices", prices.len());
+
+    Ok(())
+}
+
+/// Id of the `tickers` row of a pair, added along with its provider and
+/// currencies when the seed data does not have it. Added currencies are venue
+/// codes and not canonical, see `AssetRegistry`.
+async fn create_ticker(
+    transaction: &mut Transaction<'_, Sqlite>,
+    source: &str,
+    base: &str,
+    quote: &str,
+) -> Result<i64> {
+    if let Some(ticker_id) = find_ticker(&mut **transaction, source, base, quote).await? {
+        return Ok(ticker_id);
+    }
+
+    sqlx::query!(
+        "INSERT INTO providers (id, name)
+         SELECT (SELECT COALESCE(MAX(id), 0) + 1 FROM providers), ?
+         WHERE NOT EXISTS (SELECT 1 FROM providers WHERE lower(name) = lower(?))",
+        source,
+        source,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    for code in [base, quote] {
+        sqlx::query!(
+            "INSERT INTO currencies (id, symbol, canonical)
+             SELECT (SELECT COALESCE(MAX(id), 0) + 1 FROM currencies), ?, FALSE
+             WHERE NOT EXISTS (SELECT 1 FROM currencies WHERE symbol = ?)",
+            code,
+            code,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    sqlx::query!(
+        "INSERT INTO tickers (id, provider_id, base_id, quote_id)
+         SELECT COALESCE(MAX(id), 0) + 1,
+           (SELECT id FROM providers WHERE lower(name) = lower(?)),
+           (SELECT id FROM currencies WHERE symbol = ?),
+           (SELECT id FROM currencies WHERE symbol = ?)
+         FROM tickers",
+        source,
+        base,
+        quote,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    find_ticker(&mut **transaction, source, base, quote)
+        .await?
+        .ok_or_else(|| eyre!("No ticker for {} {}-{}", source, base, quote))
+}
+
+async fn find_ticker<'e, E>(
+    executor: E,
+    source: &str,
+    base: &str,
+    quote: &str,
+) -> Result<Option<i64>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let ticker_id = sqlx::query_scalar!(
+        r#"SELECT tickers.id AS "id!: i64"
+           FROM tickers
+           JOIN providers ON providers.id = tickers.provider_id
+           JOIN currencies base ON base.id = tickers.base_id
+           JOIN currencies quote ON quote.id = tickers.quote_id
+           WHERE lower(providers.name) = lower(?) AND base.symbol = ? AND quote.symbol = ?"#,
+        source,
+        base,
+        quote,
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(ticker_id)
+}
+
+/// Saved prices, newest first
+pub async fn fetch_prices(
+    db_connection: &SqlitePool,
+    source: &str,
+    base: &str,
+    quote: &str,
+    limit: i64,
+) -> Result<Vec<PricePoint>> {
+    let ticker_id = match find_ticker(db_connection, source, base, quote).await? {
+        Some(ticker_id) => ticker_id,
+        None => return Ok(vec![]),
+    };
+
+    let rows = sqlx::query!(
+        r#"SELECT amount, scale,
+             CAST(ROUND((julianday(created_at) - 2440587.5) * 86400000) AS INTEGER) AS "time!: i64"
+           FROM ticker_prices
+           WHERE ticker_id = ?
+           ORDER BY created_at DESC
+           LIMIT ?"#,
+        ticker_id,
+        limit,
+    )
+    .fetch_all(db_connection)
+    .await?;
+
+    let prices = rows
+        .into_iter()
+        .map(|row| PricePoint {
+            source: source.to_string(),
+            symbol: format!("{}-{}", base, quote),
+            // Keeps the scale, so the price prints as the venue sent it
+            price: Decimal::from_i128_with_scale(row.amount.into(), row.scale as u32),
+            time: row.time,
+        })
+        .collect();
+
+    Ok(prices)
+}
@@ -0,0 +1,151 @@
+use rand::Rng;
+use tokio::time::Duration;
+
+use crate::config::Config;
+
+/// How a feed waits between reconnect attempts. Delays grow exponentially from
+/// `initial_delay` up to `max_delay` and get randomized by `jitter`, so that
+/// connections which dropped together don't all reconnect at the same moment.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub multiplier: f64,
+    pub max_delay: Duration,
+    /// Fraction of the delay (0.0 - 1.0) which is randomly taken off
+    pub jitter: f64,
+    /// Consecutive failed attempts after which the feed is considered dead
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    pub fn new(config: &Config) -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(config.reconnect_initial_delay_ms),
+            multiplier: config.reconnect_multiplier.max(1.0),
+            max_delay: Duration::from_millis(config.reconnect_max_delay_ms),
+            jitter: config.reconnect_jitter.clamp(0.0, 1.0),
+            max_attempts: match config.reconnect_max_attempts {
+                0 => None,
+                attempts => Some(attempts),
+            },
+        }
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            policy: self.clone(),
+            attempts: 0,
+        }
+    }
+}
+
+/// Reconnect state of a single feed connection
+#[derive(Debug)]
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempts: u32,
+}
+
+impl Backoff {
+    /// Delay before the next attempt, `None` once `max_attempts` is used up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempts >= max_attempts {
+                return None;
+            }
+        }
+
+        let exponent = i32::try_from(self.attempts).unwrap_or(i32::MAX);
+        let delay = (self.policy.initial_delay.as_secs_f64()
+            * self.policy.multiplier.powi(exponent))
+        .min(self.policy.max_delay.as_secs_f64());
+        let jitter = rand::thread_rng().gen_range(0.0..=self.policy.jitter);
+
+        self.attempts += 1;
+
+        Some(Duration::from_secs_f64(delay * (1.0 - jitter)))
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Called once a connection is established
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64, max_attempts: Option<u32>) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_delay: Duration::from_millis(1000),
+            jitter,
+            max_attempts,
+        }
+    }
+
+    fn millis(delay: Option<Duration>) -> u128 {
+        delay.unwrap().as_millis()
+    }
+
+    #[test]
+    fn delay_grows_up_to_the_cap() {
+        let mut backoff = policy(0.0, None).backoff();
+
+        let delays = (0..7)
+            .map(|_| millis(backoff.next_delay()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000, 1000]);
+        assert_eq!(backoff.attempts(), 7);
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let mut backoff = policy(0.5, None).backoff();
+
+        for attempt in 0..100 {
+            let delay = millis(backoff.next_delay());
+            let full = (100 << attempt.min(4)).min(1000);
+
+            assert!(
+                (full / 2..=full).contains(&delay),
+                "attempt {}: {}ms outside {}..={}",
+                attempt,
+                delay,
+                full / 2,
+                full
+            );
+        }
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut backoff = policy(0.0, None).backoff();
+        backoff.next_delay();
+        backoff.next_delay();
+
+        backoff.reset();
+
+        assert_eq!(backoff.attempts(), 0);
+        assert_eq!(millis(backoff.next_delay()), 100);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut backoff = policy(0.0, Some(2)).backoff();
+
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert_eq!(backoff.next_delay(), None);
+
+        backoff.reset();
+        assert!(backoff.next_delay().is_some());
+    }
+}
@@ -0,0 +1,150 @@
+use chrono::Utc;
+use eyre::{Result, WrapErr};
+use flate2::{write::GzEncoder, Compression};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::services::symbols::SymbolInfo;
+
+/// Entries waiting for the journal writer, newer ones are dropped while it is
+/// full
+const JOURNAL_BUFFER: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// Raw frame received by a connector
+    #[default]
+    Frame,
+    /// Markets of a source as fetched from the venue metadata endpoint, a
+    /// JSON list of `SymbolInfo`
+    Symbols,
+}
+
+impl EntryKind {
+    fn is_frame(&self) -> bool {
+        *self == EntryKind::Frame
+    }
+}
+
+/// One JSON line of a journal
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JournalEntry {
+    /// Receive time in milliseconds since the epoch
+    pub time: i64,
+    /// Name of the connector which received the frame, or the source of the
+    /// symbols
+    pub feed: String,
+    #[serde(default, skip_serializing_if = "EntryKind::is_frame")]
+    pub kind: EntryKind,
+    pub data: String,
+}
+
+/// Writes every raw frame received by the connectors, and the venue markets
+/// needed to decode them, to a gzipped journal which can be played back with
+/// `run_replay`
+#[derive(Clone)]
+pub struct FeedRecorder {
+    entry_tx: mpsc::Sender<JournalEntry>,
+    // Entries dropped since the writer last flushed
+    dropped: Arc<AtomicU64>,
+}
+
+impl FeedRecorder {
+    /// Creates `feeds-<timestamp>.jsonl.gz` in `dir`
+    pub fn start(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", dir))?;
+
+        let file_name = format!("feeds-{}.jsonl.gz", Utc::now().format("%Y%m%dT%H%M%S"));
+        let path = Path::new(dir).join(file_name);
+        let file =
+            File::create(&path).wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+        let encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+
+        let (entry_tx, entry_rx) = mpsc::channel(JOURNAL_BUFFER);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        // Compression and file writes block, so they get a thread of their own
+        let writer_dropped = dropped.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = write_journal(encoder, entry_rx, &writer_dropped) {
+                warn!("Feed recorder stopped: {}", err);
+            }
+        });
+
+        info!("Recording feeds to {}", path.display());
+
+        Ok(FeedRecorder { entry_tx, dropped })
+    }
+
+    pub fn record(&self, feed: &str, data: &str) {
+        if !self.write(feed, EntryKind::Frame, data.to_string()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_symbols(&self, source: &str, symbols: &[SymbolInfo]) {
+        match serde_json::to_string(symbols) {
+            Ok(data) => {
+                // Frames of the source can't be replayed without them
+                if !self.write(source, EntryKind::Symbols, data) {
+                    warn!(
+                        "Failed to record {} symbols, the recorder is behind",
+                        source
+                    );
+                }
+            }
+            Err(err) => warn!("Failed to record {} symbols: {}", source, err),
+        }
+    }
+
+    /// Queues an entry, false when the queue is full. A closed queue means the
+    /// writer is gone, which is logged there.
+    fn write(&self, feed: &str, kind: EntryKind, data: String) -> bool {
+        let entry = JournalEntry {
+            time: Utc::now().timestamp_millis(),
+            feed: feed.to_string(),
+            kind,
+            data,
+        };
+
+        !matches!(self.entry_tx.try_send(entry), Err(TrySendError::Full(_)))
+    }
+}
+
+fn write_journal(
+    mut encoder: GzEncoder<BufWriter<File>>,
+    mut entry_rx: mpsc::Receiver<JournalEntry>,
+    dropped: &AtomicU64,
+) -> io::Result<()> {
+    while let Some(entry) = entry_rx.blocking_recv() {
+        let mut entry = Some(entry);
+
+        while let Some(current) = entry {
+            serde_json::to_writer(&mut encoder, &current)?;
+            encoder.write_all(b"\n")?;
+            entry = entry_rx.try_recv().ok();
+        }
+
+        // Flushing once the queue is drained keeps the journal readable up to
+        // the last frame when the process dies
+        encoder.flush()?;
+
+        let dropped = dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("Dropped {} frames, the feed recorder is behind", dropped);
+        }
+    }
+
+    encoder.finish()?.flush()
+}
@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use eyre::{bail, Result, WrapErr};
+use flate2::read::GzDecoder;
+use log::{info, warn};
+use redis::Client as RedisClient;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    sync::Arc,
+};
+use tokio::{
+    sync::mpsc,
+    time::{sleep_until, Duration, Instant},
+};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::services::{
+    connector::{apply_snapshot, handle_frame, ExchangeConnector, FeedBooks, FeedEvent},
+    order_book::BookUpdate,
+    recorder::{EntryKind, JournalEntry},
+    symbols::SymbolInfo,
+};
+use crate::AppContext;
+
+/// Stands in for a live connector when its recorded frames are played back.
+/// Frames are decoded by the wrapped connector, nothing is sent to the venue
+/// and nothing is fetched from it.
+pub struct ReplayConnector {
+    inner: Arc<dyn ExchangeConnector>,
+}
+
+impl ReplayConnector {
+    pub fn new(inner: Arc<dyn ExchangeConnector>) -> Self {
+        ReplayConnector { inner }
+    }
+}
+
+#[async_trait]
+impl ExchangeConnector for ReplayConnector {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn url(&self, symbols: &[String]) -> String {
+        self.inner.url(symbols)
+    }
+
+    fn subscribe_messages(&self, _symbols: &[String]) -> Result<Vec<Message>> {
+        Ok(vec![])
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<FeedEvent>> {
+        self.inner.decode(data)
+    }
+
+    fn serves_tickers(&self) -> bool {
+        self.inner.serves_tickers()
+    }
+
+    // Markets come from the journal, as they were when it was recorded
+    async fn fetch_symbols(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    // A live snapshot would not match the recorded diffs
+    async fn fetch_book_snapshot(&self, symbol: &str) -> Result<BookUpdate> {
+        bail!(
+            "{} {} order book snapshots are not available in replays",
+            self.name(),
+            symbol
+        )
+    }
+}
+
+/// Plays a journal written by `FeedRecorder` back into the pipeline. `speed`
+/// of 1 keeps the original pace, 2 plays twice as fast, 0 skips the delays.
+pub async fn run_replay(
+    app_context: AppContext,
+    connectors: Vec<Arc<dyn ExchangeConnector>>,
+    path: String,
+    speed: f64,
+) -> Result<()> {
+    let mut redis_connection =
+        RedisClient::open(app_context.config.redis_url.as_str())?.get_connection()?;
+
+    let replay_connectors = connectors
+        .into_iter()
+        .map(|connector| (connector.name(), ReplayConnector::new(connector)))
+        .collect::<HashMap<_, _>>();
+
+    let file = File::open(&path).wrap_err_with(|| format!("Failed to open journal {}", path))?;
+    let (entry_tx, mut entry_rx) = mpsc::channel(1024);
+    tokio::task::spawn_blocking(move || read_journal(file, entry_tx));
+
+    info!("Replaying {} at {}x speed", path, speed);
+
+    let started = Instant::now();
+    let mut first_time = None;
+    let mut unknown_feeds = HashSet::new();
+    let mut books = FeedBooks::default();
+    let mut frames = 0;
+
+    while let Some(entry) = entry_rx.recv().await {
+        let first_time = *first_time.get_or_insert(entry.time);
+
+        if speed > 0.0 {
+            let offset_ms = (entry.time - first_time).max(0) as f64 / speed;
+            sleep_until(started + Duration::from_secs_f64(offset_ms / 1000.0)).await;
+        }
+
+        // Venues need their markets to decode frames
+        if entry.kind == EntryKind::Symbols {
+            match serde_json::from_str::<Vec<SymbolInfo>>(&entry.data) {
+                Ok(symbols) => app_context.symbols.replace(&entry.feed, symbols),
+                Err(err) => warn!("Invalid {} symbols in journal: {}", entry.feed, err),
+            }
+            continue;
+        }
+
+        let connector = match replay_connectors.get(entry.feed.as_str()) {
+            Some(connector) => connector,
+            None => {
+                if unknown_feeds.insert(entry.feed.clone()) {
+                    warn!("Skipping frames of unknown feed {}", entry.feed);
+                }
+                continue;
+            }
+        };
+
+        if let Err(err) = handle_frame(
+            &app_context,
+            &mut redis_connection,
+            connector,
+            &entry.data,
+            &mut books,
+        ) {
+            warn!(
+                "Failed to replay {} frame {}: {}",
+                entry.feed, entry.data, err
+            );
+        }
+
+        for symbol in books.snapshots.drain(..) {
+            let snapshot = connector.fetch_book_snapshot(&symbol).await;
+            apply_snapshot(&app_context, connector, &symbol, snapshot);
+        }
+
+        frames += 1;
+    }
+
+    info!("Replayed {} frames from {}", frames, path);
+
+    Ok(())
+}
+
+fn read_journal(file: File, entry_tx: mpsc::Sender<JournalEntry>) {
+    for line in BufReader::new(GzDecoder::new(file)).lines() {
+        let line = match line {
+            Ok(line) => line,
+            // Journals of processes which died end abruptly
+            Err(err) => {
+                warn!("Journal ended early: {}", err);
+                break;
+            }
+        };
+
+        match serde_json::from_str(&line) {
+            Ok(entry) => {
+                if entry_tx.blocking_send(entry).is_err() {
+                    break;
+                }
+            }
+            Err(err) => warn!("Invalid journal entry: {}", err),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use async_graphql::{Enum, SimpleObject};
+use chrono::Utc;
+use log::{info, warn};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+use tokio::{
+    sync::watch,
+    time::{sleep, Duration},
+};
+
+use crate::services::{
+    connector::{run_connector, ExchangeConnector},
+    fallback::run_fallback,
+    reconnect::ReconnectPolicy,
+};
+use crate::AppContext;
+
+/// A feed which stayed up this long gets its restart backoff reset
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+#[derive(Enum, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedState {
+    Connecting,
+    Live,
+    /// No data within the stale timeout, about to reconnect
+    Stale,
+    /// The connector task failed and waits for a restart
+    Failed,
+    /// Gave up after `RECONNECT_MAX_ATTEMPTS` failed attempts, not restarted
+    Dead,
+}
+
+#[derive(Serialize, SimpleObject, Debug, Clone)]
+pub struct FeedStatus {
+    /// Unique per connection, e.g. `Binance #3`
+    pub feed: String,
+    pub state: FeedState,
+    /// Time of the last state change in milliseconds since the epoch
+    pub since: i64,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// Time the feed last stopped being live in milliseconds since the epoch,
+    /// empty while it is live
+    pub down_since: Option<i64>,
+    /// Whether tickers are polled over REST in the meantime
+    pub fallback: bool,
+    /// Whether the feed publishes tickers, see `ExchangeConnector::serves_tickers`
+    pub serves_tickers: bool,
+}
+
+/// Starts the connector tasks, restarts them with backoff when they fail and
+/// keeps track of the state of every feed
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    feeds: Arc<RwLock<BTreeMap<String, FeedStatus>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+/// Lets a connector task report the state of its feed
+#[derive(Clone)]
+pub struct FeedHandle {
+    feed: String,
+    supervisor: Supervisor,
+}
+
+impl FeedHandle {
+    pub fn set_state(&self, state: FeedState) {
+        self.supervisor.update(&self.feed, |status| {
+            if status.state != state {
+                let now = Utc::now().timestamp_millis();
+
+                if state == FeedState::Live {
+                    status.down_since = None;
+                } else if status.state == FeedState::Live {
+                    status.down_since = Some(now);
+                }
+                status.state = state;
+                status.since = now;
+            }
+        });
+    }
+
+    pub fn set_fallback(&self, fallback: bool) {
+        self.supervisor
+            .update(&self.feed, |status| status.fallback = fallback);
+    }
+
+    pub fn status(&self) -> Option<FeedStatus> {
+        self.supervisor
+            .feeds
+            .read()
+            .unwrap()
+            .get(&self.feed)
+            .cloned()
+    }
+
+    pub fn set_error(&self, error: String) {
+        self.supervisor
+            .update(&self.feed, |status| status.last_error = Some(error));
+    }
+}
+
+impl Supervisor {
+    pub fn spawn(
+        &self,
+        app_context: AppContext,
+        connector: Arc<dyn ExchangeConnector>,
+        symbols: watch::Receiver<Vec<String>>,
+    ) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let feed = format!("{} #{}", connector.name(), id);
+
+        let now = Utc::now().timestamp_millis();
+        self.feeds.write().unwrap().insert(
+            feed.clone(),
+            FeedStatus {
+                feed: feed.clone(),
+                state: FeedState::Connecting,
+                since: now,
+                restarts: 0,
+                last_error: None,
+                down_since: Some(now),
+                fallback: false,
+                serves_tickers: connector.serves_tickers(),
+            },
+        );
+
+        let handle = FeedHandle {
+            feed,
+            supervisor: self.clone(),
+        };
+
+        if app_context.config.fallback_after_secs > 0 && connector.poll_interval().is_some() {
+            tokio::task::spawn(run_fallback(
+                app_context.clone(),
+                connector.clone(),
+                symbols.clone(),
+                handle.clone(),
+            ));
+        }
+        tokio::task::spawn(supervise(app_context, connector, symbols, handle));
+    }
+
+    pub fn feeds(&self) -> Vec<FeedStatus> {
+        self.feeds.read().unwrap().values().cloned().collect()
+    }
+
+    fn update(&self, feed: &str, update: impl FnOnce(&mut FeedStatus)) {
+        if let Some(status) = self.feeds.write().unwrap().get_mut(feed) {
+            update(status);
+        }
+    }
+}
+
+async fn supervise(
+    app_context: AppContext,
+    connector: Arc<dyn ExchangeConnector>,
+    symbols: watch::Receiver<Vec<String>>,
+    handle: FeedHandle,
+) {
+    let reconnect_policy = ReconnectPolicy::new(&app_context.config);
+    // Restarts give up after `max_attempts` like reconnects do
+    let mut backoff = reconnect_policy.backoff();
+
+    loop {
+        let started = tokio::time::Instant::now();
+
+        // Running the connector in a task of its own turns panics into errors
+        let result = tokio::task::spawn(run_connector(
+            app_context.clone(),
+            connector.clone(),
+            symbols.clone(),
+            reconnect_policy.clone(),
+            handle.clone(),
+        ))
+        .await;
+
+        let error = match result {
+            Ok(Ok(())) => {
+                info!("{} stopped", handle.feed);
+                return;
+            }
+            Ok(Err(err)) => format!("{:#}", err),
+            Err(err) => format!("Task panicked: {}", err),
+        };
+
+        warn!("{} failed: {}", handle.feed, error);
+        handle.set_error(error);
+
+        // The connector ran out of reconnect attempts
+        if handle.status().map(|status| status.state) == Some(FeedState::Dead) {
+            warn!("{} is dead, not restarting it", handle.feed);
+            return;
+        }
+        handle.set_state(FeedState::Failed);
+
+        if started.elapsed() >= HEALTHY_RUN {
+            backoff.reset();
+        }
+        let delay = match backoff.next_delay() {
+            Some(delay) => delay,
+            None => {
+                warn!(
+                    "{} is dead after {} restarts, not restarting it",
+                    handle.feed,
+                    backoff.attempts()
+                );
+                handle.set_state(FeedState::Dead);
+                return;
+            }
+        };
+
+        info!(
+            "Restarting {} in {:.1} seconds",
+            handle.feed,
+            delay.as_secs_f64()
+        );
+        sleep(delay).await;
+
+        handle
+            .supervisor
+            .update(&handle.feed, |status| status.restarts += 1);
+    }
+}
@@ -0,0 +1,384 @@
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::config::Config;
+use crate::services::{assets::AssetRegistry, recorder::FeedRecorder};
+
+/// Market as listed by the venue metadata endpoint (Binance `exchangeInfo`,
+/// Coinbase `products`, etc.)
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SymbolInfo {
+    pub source: String,
+    /// Symbol as used on the venue feed, e.g. `BTCUSDT` on Binance
+    pub venue_symbol: String,
+    /// Canonical asset codes, see `AssetRegistry`
+    pub base: String,
+    pub quote: String,
+    /// Trading status as reported by the venue
+    pub status: String,
+    /// Smallest price increment
+    pub tick_size: Option<String>,
+    /// Smallest quantity increment
+    pub step_size: Option<String>,
+    /// Smallest order value in quote currency
+    pub min_notional: Option<String>,
+}
+
+impl SymbolInfo {
+    pub fn new(source: &str, venue_symbol: &str, base: &str, quote: &str, status: &str) -> Self {
+        SymbolInfo {
+            source: source.to_string(),
+            venue_symbol: venue_symbol.to_string(),
+            base: base.to_string(),
+            quote: quote.to_string(),
+            status: status.to_string(),
+            tick_size: None,
+            step_size: None,
+            min_notional: None,
+        }
+    }
+}
+
+/// Pattern which applies to one source, or to all of them
+#[derive(Debug, Clone)]
+struct SourcePattern {
+    source: Option<String>,
+    pattern: String,
+}
+
+impl SourcePattern {
+    fn applies_to(&self, source: &str) -> bool {
+        self.source.as_ref().map_or(true, |pattern_source| {
+            pattern_source.eq_ignore_ascii_case(source)
+        })
+    }
+}
+
+/// Comma separated `pattern` or `source:pattern` entries
+fn parse_patterns(value: &str) -> Vec<SourcePattern> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((source, pattern)) => SourcePattern {
+                source: Some(source.trim().to_lowercase()),
+                pattern: pattern.trim().to_uppercase(),
+            },
+            None => SourcePattern {
+                source: None,
+                pattern: entry.to_uppercase(),
+            },
+        })
+        .collect()
+}
+
+/// Matches `*` (any run of characters) and `?` (any one character)
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it was matched against
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Markets to subscribe to, from `SYMBOL_INCLUDE`, `SYMBOL_EXCLUDE` and
+/// `SYMBOL_QUOTES`. Patterns are globs on the canonical `BASE-QUOTE` symbol,
+/// prefixed with `source:` to only apply to one venue.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolFilter {
+    include: Vec<SourcePattern>,
+    exclude: Vec<SourcePattern>,
+    quotes: Vec<SourcePattern>,
+}
+
+impl SymbolFilter {
+    pub fn new(config: &Config) -> Self {
+        SymbolFilter {
+            include: parse_patterns(&config.symbol_include),
+            exclude: parse_patterns(&config.symbol_exclude),
+            quotes: parse_patterns(&config.symbol_quotes),
+        }
+    }
+
+    /// Whether `symbol` (`BASE-QUOTE`) of `source` is subscribed to, sources
+    /// without include patterns or quotes take everything not excluded
+    pub fn allows(&self, source: &str, symbol: &str, quote: &str) -> bool {
+        let matches = |patterns: &[SourcePattern], text: &str| -> Option<bool> {
+            let mut patterns = patterns
+                .iter()
+                .filter(|pattern| pattern.applies_to(source))
+                .peekable();
+            patterns.peek()?;
+
+            Some(patterns.any(|pattern| glob_match(&pattern.pattern, text)))
+        };
+
+        let symbol = symbol.to_uppercase();
+
+        matches(&self.include, &symbol).unwrap_or(true)
+            && matches(&self.quotes, &quote.to_uppercase()).unwrap_or(true)
+            && !matches(&self.exclude, &symbol).unwrap_or(false)
+    }
+}
+
+/// Values keyed by source and venue symbol
+type PerSource<T> = HashMap<String, HashMap<String, T>>;
+
+/// Markets of every venue, keyed by source and venue symbol. Connectors fill
+/// it when fetching their symbols and use it to decode tickers. Markets go to
+/// the journal when recording, so that replays decode without the venues.
+#[derive(Clone, Default)]
+pub struct SymbolRegistry {
+    symbols: Arc<RwLock<PerSource<SymbolInfo>>>,
+    // Venue symbols missing from `symbols` as split by `split`
+    splits: Arc<RwLock<PerSource<Option<SymbolInfo>>>>,
+    assets: AssetRegistry,
+    filter: SymbolFilter,
+    recorder: Option<FeedRecorder>,
+}
+
+impl SymbolRegistry {
+    pub fn new(
+        assets: AssetRegistry,
+        filter: SymbolFilter,
+        recorder: Option<FeedRecorder>,
+    ) -> Self {
+        SymbolRegistry {
+            symbols: Arc::new(RwLock::new(HashMap::new())),
+            splits: Arc::new(RwLock::new(HashMap::new())),
+            assets,
+            filter,
+            recorder,
+        }
+    }
+
+    /// Canonical code of an asset as named by `source`, see `AssetRegistry`
+    pub fn canonical(&self, source: &str, code: &str) -> String {
+        self.assets.canonical(source, code)
+    }
+
+    /// Whether connectors should subscribe to the market, see `SymbolFilter`
+    pub fn allows(&self, info: &SymbolInfo) -> bool {
+        let base = self.assets.canonical(&info.source, &info.base);
+        let quote = self.assets.canonical(&info.source, &info.quote);
+
+        self.filter
+            .allows(&info.source, &format!("{}-{}", base, quote), &quote)
+    }
+
+    /// Replaces the markets of `source`, dropping the delisted ones. Base and
+    /// quote are translated to the canonical asset codes.
+    pub fn replace(&self, source: &str, symbols: Vec<SymbolInfo>) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_symbols(source, &symbols);
+        }
+
+        let symbols = symbols
+            .into_iter()
+            .map(|mut info| {
+                info.base = self.assets.canonical(source, &info.base);
+                info.quote = self.assets.canonical(source, &info.quote);
+
+                (info.venue_symbol.clone(), info)
+            })
+            .collect();
+
+        self.symbols
+            .write()
+            .unwrap()
+            .insert(source.to_string(), symbols);
+        self.splits.write().unwrap().remove(source);
+    }
+
+    /// Market of a venue symbol. Symbols missing from the registry, e.g. in
+    /// replays of journals recorded without markets, are split into base and
+    /// quote instead, once per symbol until the markets of the source change.
+    pub fn get(&self, source: &str, venue_symbol: &str) -> Option<SymbolInfo> {
+        let info = self
+            .symbols
+            .read()
+            .unwrap()
+            .get(source)
+            .and_then(|symbols| symbols.get(venue_symbol))
+            .cloned();
+        if info.is_some() {
+            return info;
+        }
+
+        let split = self
+            .splits
+            .read()
+            .unwrap()
+            .get(source)
+            .and_then(|splits| splits.get(venue_symbol))
+            .cloned();
+
+        split.unwrap_or_else(|| {
+            let info = self.split(source, venue_symbol);
+            self.splits
+                .write()
+                .unwrap()
+                .entry(source.to_string())
+                .or_default()
+                .insert(venue_symbol.to_string(), info.clone());
+
+            info
+        })
+    }
+
+    /// `BTC-USDT`, `BTC/USD` or `BTC_USDT` split at the separator, `BTCUSDT`
+    /// at the longest known asset it ends with
+    fn split(&self, source: &str, venue_symbol: &str) -> Option<SymbolInfo> {
+        let symbol = venue_symbol.to_uppercase();
+        let (base, quote) = match symbol.split_once(['-', '/', '_']) {
+            Some(parts) => parts,
+            None => self
+                .assets
+                .all()
+                .into_iter()
+                .filter_map(|asset| {
+                    let base = symbol.strip_suffix(asset.symbol.as_str())?;
+                    (!base.is_empty()).then(|| (base, &symbol[base.len()..]))
+                })
+                .max_by_key(|(_, quote)| quote.len())?,
+        };
+        if base.is_empty() || quote.is_empty() {
+            return None;
+        }
+
+        Some(SymbolInfo::new(
+            source,
+            venue_symbol,
+            &self.assets.canonical(source, base),
+            &self.assets.canonical(source, quote),
+            "unknown",
+        ))
+    }
+
+    pub fn all(&self, source: Option<&str>) -> Vec<SymbolInfo> {
+        self.symbols
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(symbols_source, _)| source.map_or(true, |source| source == *symbols_source))
+            .flat_map(|(_, symbols)| symbols.values().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &str, exclude: &str, quotes: &str) -> SymbolFilter {
+        SymbolFilter {
+            include: parse_patterns(include),
+            exclude: parse_patterns(exclude),
+            quotes: parse_patterns(quotes),
+        }
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        let cases = [
+            ("BTC-USDT", "BTC-USDT", true),
+            ("BTC-USDT", "BTC-USD", false),
+            ("*", "", true),
+            ("*", "ETH-BTC", true),
+            ("BTC-*", "BTC-USDT", true),
+            ("BTC-*", "BTC-", true),
+            ("BTC-*", "ETH-USDT", false),
+            ("*-USDT", "ETH-USDT", true),
+            ("*-USDT", "ETH-USDC", false),
+            ("*USD*", "BTC-USDT", true),
+            ("*T*T", "BTC-USDT", true),
+            ("BTC-USD?", "BTC-USDT", true),
+            ("BTC-USD?", "BTC-USD", false),
+            ("???-USDT", "ETH-USDT", true),
+            ("???-USDT", "DOGE-USDT", false),
+            ("?", "", false),
+            ("", "", true),
+            ("", "BTC", false),
+        ];
+
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_match(pattern, text),
+                expected,
+                "{} against {}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn empty_filter_allows_everything() {
+        let empty = SymbolFilter::default();
+
+        assert!(empty.allows("binance", "BTC-USDT", "USDT"));
+        assert!(empty.allows("kraken", "DOGE-EUR", "EUR"));
+
+        let blank = filter(" , ", "", "");
+        assert!(blank.allows("binance", "BTC-USDT", "USDT"));
+    }
+
+    #[test]
+    fn include_and_quotes_narrow_down() {
+        let filter = filter("BTC-*,ETH-*", "", "USDT,USD");
+
+        assert!(filter.allows("binance", "BTC-USDT", "USDT"));
+        assert!(filter.allows("coinbase", "eth-usd", "usd"));
+        assert!(!filter.allows("binance", "BTC-EUR", "EUR"));
+        assert!(!filter.allows("binance", "SOL-USDT", "USDT"));
+    }
+
+    #[test]
+    fn exclude_overrides_include() {
+        let filter = filter("BTC-*", "*-EUR,BTC-USDC", "");
+
+        assert!(filter.allows("binance", "BTC-USDT", "USDT"));
+        assert!(!filter.allows("binance", "BTC-EUR", "EUR"));
+        assert!(!filter.allows("binance", "BTC-USDC", "USDC"));
+    }
+
+    #[test]
+    fn source_patterns_only_apply_to_their_source() {
+        let filter = filter("binance:BTC-*", "kraken:*", "coinbase:USD");
+
+        assert!(filter.allows("binance", "BTC-USDT", "USDT"));
+        assert!(!filter.allows("binance", "ETH-USDT", "USDT"));
+        assert!(filter.allows("Coinbase", "ETH-USD", "USD"));
+        assert!(!filter.allows("coinbase", "ETH-EUR", "EUR"));
+        assert!(!filter.allows("kraken", "BTC-USD", "USD"));
+        assert!(filter.allows("okx", "SOL-EUR", "EUR"));
+    }
+}
@@ -0,0 +1,44 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::services::ws_message::WsMessage;
+
+/// Latest ticker of every (source, symbol), keyed the same way as in Redis
+#[derive(Clone, Default)]
+pub struct TickerStore {
+    tickers: Arc<RwLock<HashMap<String, WsMessage>>>,
+}
+
+impl TickerStore {
+    pub fn update(&self, ws_message: &WsMessage) {
+        self.tickers
+            .write()
+            .unwrap()
+            .insert(ws_message.get_key(), ws_message.clone());
+    }
+
+    pub fn get(&self, source: &str, base: &str, quote: &str) -> Option<WsMessage> {
+        let key = format!("{}-{}-{}", source, base, quote);
+
+        self.tickers.read().unwrap().get(&key).cloned()
+    }
+
+    /// Latest tickers of an instrument on every source
+    pub fn instrument(&self, base: &str, quote: &str) -> Vec<WsMessage> {
+        let symbol = format!("{}-{}", base, quote);
+
+        self.tickers
+            .read()
+            .unwrap()
+            .values()
+            .filter(|ticker| ticker.get_symbol() == symbol)
+            .cloned()
+            .collect()
+    }
+
+    pub fn all(&self) -> Vec<WsMessage> {
+        self.tickers.read().unwrap().values().cloned().collect()
+    }
+}
@@ -0,0 +1,61 @@
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+/// Single trade (print), sent to the websocket clients subscribed to trades
+#[derive(Serialize, Clone, Debug)]
+pub struct Trade {
+    #[serde(rename = "type")]
+    pub message_type: &'static str,
+    pub source: String,
+    pub base: String,
+    pub quote: String,
+    pub trade_id: String,
+    pub price: String,
+    pub size: String,
+    /// Side of the taker
+    pub side: TradeSide,
+    /// Exchange trade time in milliseconds since the epoch
+    pub exchange_time: Option<i64>,
+}
+
+impl Trade {
+    pub fn new(
+        source: &str,
+        symbol: &str,
+        trade_id: String,
+        price: String,
+        size: String,
+        side: TradeSide,
+    ) -> Self {
+        let (base, quote) = symbol.split_once('-').unwrap_or((symbol, ""));
+
+        Trade {
+            message_type: "trade",
+            source: source.to_string(),
+            base: base.to_string(),
+            quote: quote.to_string(),
+            trade_id,
+            price,
+            size,
+            side,
+            exchange_time: None,
+        }
+    }
+}
+
+impl fmt::Display for Trade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "(Trade: {} - {}-{} - {:?} {} @ {})",
+            self.source, self.base, self.quote, self.side, self.size, self.price
+        )
+    }
+}
@@ -9,9 +9,33 @@ use futures_util::{
     sink::SinkExt,
     stream::{SplitSink, SplitStream, StreamExt},
 };
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashSet;
+use tokio::sync::{broadcast::error::RecvError, watch};
 
 use crate::AppContext;
 
+const TICKERS_CHANNEL: &str = "tickers";
+const ORDER_BOOKS_CHANNEL: &str = "order_books";
+const TRADES_CHANNEL: &str = "trades";
+const CANDLES_CHANNEL: &str = "candles";
+
+/// Message sent by the websocket client, e.g.
+/// `{"event": "subscribe", "data": {"channels": ["order_books"]}}`
+#[derive(Deserialize, Debug)]
+struct ClientMessage {
+    event: String,
+    #[serde(default)]
+    data: ChannelsData,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ChannelsData {
+    #[serde(default)]
+    channels: Vec<String>,
+}
+
 pub async fn websocket_handler(ws: WebSocketUpgrade, State(state): State<AppContext>) -> Response {
     ws.on_upgrade(|socket| handle_socket(socket, state))
 }
@@ -19,22 +43,82 @@ pub async fn websocket_handler(ws: WebSocketUpgrade, State(state): State<AppCont
 async fn handle_socket(socket: WebSocket, state: AppContext) {
     let (sender, receiver) = socket.split();
 
-    tokio::spawn(write(sender, state));
-    tokio::spawn(read(receiver));
+    // Clients get tickers until they subscribe to something else
+    let (channels_tx, channels_rx) = watch::channel(HashSet::from([TICKERS_CHANNEL.to_string()]));
+
+    tokio::spawn(write(sender, state, channels_rx));
+    tokio::spawn(read(receiver, channels_tx));
 }
 
-async fn read(_receiver: SplitStream<WebSocket>) {
-    // ...
+async fn read(mut receiver: SplitStream<WebSocket>, channels_tx: watch::Sender<HashSet<String>>) {
+    while let Some(Ok(message)) = receiver.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let client_message: ClientMessage = match serde_json::from_str(&text) {
+            Ok(client_message) => client_message,
+            Err(err) => {
+                warn!("Invalid websocket client message: {}", err);
+                continue;
+            }
+        };
+
+        match client_message.event.as_str() {
+            "subscribe" => channels_tx.send_modify(|channels| {
+                channels.extend(client_message.data.channels);
+            }),
+            "unsubscribe" => channels_tx.send_modify(|channels| {
+                for channel in &client_message.data.channels {
+                    channels.remove(channel);
+                }
+            }),
+            event => warn!("Unknown websocket client event: {}", event),
+        }
+    }
 }
 
-async fn write(mut sender: SplitSink<WebSocket, Message>, state: AppContext) {
-    let mut rx = state.ticker_tx.subscribe();
-    while let Ok(msg) = rx.recv().await {
-        sender
-            .send(Message::Text(msg.to_string()))
-            .await
-            .expect("Error while sending message");
+async fn write(
+    mut sender: SplitSink<WebSocket, Message>,
+    state: AppContext,
+    mut channels: watch::Receiver<HashSet<String>>,
+) {
+    let mut ticker_rx = state.ticker_tx.subscribe();
+    let mut book_rx = state.book_tx.subscribe();
+    let mut trade_rx = state.trade_tx.subscribe();
+    let mut candle_rx = state.candle_tx.subscribe();
+
+    loop {
+        let (channel, msg) = tokio::select! {
+            msg = ticker_rx.recv() => (TICKERS_CHANNEL, msg),
+            msg = book_rx.recv() => (ORDER_BOOKS_CHANNEL, msg),
+            msg = trade_rx.recv() => (TRADES_CHANNEL, msg),
+            msg = candle_rx.recv() => (CANDLES_CHANNEL, msg),
+            // `read` drops the sender once the client is gone, which may be a
+            // client that doesn't get any of the messages sent
+            changed = channels.changed() => match changed {
+                Ok(()) => continue,
+                Err(_) => break,
+            },
+        };
+
+        let msg = match msg {
+            Ok(msg) => msg,
+            // Slow client, skip what it missed
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        };
+
+        if !channels.borrow().contains(channel) {
+            continue;
+        }
+
+        if sender.send(Message::Text(msg.to_string())).await.is_err() {
+            break;
+        }
     }
 
-    sender.close().await.unwrap();
+    sender.close().await.ok();
 }
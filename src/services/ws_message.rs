@@ -1,19 +1,82 @@
-use log::warn;
+use async_graphql::SimpleObject;
+use chrono::DateTime;
+use rust_decimal::Decimal;
 use serde::Serialize;
 use std::fmt;
 
 use crate::services::binance::BinanceMessage;
+use crate::services::bybit::BybitMessage;
 use crate::services::coinbase::CoinbaseMessage;
+use crate::services::kraken::KrakenTicker;
+use crate::services::okx::OkxTicker;
+use crate::services::symbols::SymbolInfo;
 
-#[derive(Serialize)]
+/// Ticker update sent to the websocket clients. 24h statistics are only
+/// present when the venue sends them.
+#[derive(Serialize, Clone, Debug, Default, SimpleObject)]
+#[graphql(name = "Ticker")]
 pub struct WsMessage {
     source: String,
     base: String,
     quote: String,
-    pub price: String,
+    pub price: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub high: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low: Option<String>,
+    /// 24h volume in base currency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<String>,
+    /// 24h volume in quote currency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quote_volume: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_bid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_ask: Option<String>,
+    /// Exchange event time in milliseconds since the epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<i64>,
+    /// Time the frame arrived at the server in milliseconds since the epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received_time: Option<i64>,
+    /// Time the ticker was sent to the websocket clients in milliseconds
+    /// since the epoch, empty while it was not sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_time: Option<i64>,
+    /// Polled over REST while the venue WebSocket is down
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+    /// Sources the price of a composite ticker was combined from
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
 }
 
 impl WsMessage {
+    pub fn new(source: &str, base: &str, quote: &str, price: Decimal) -> Self {
+        WsMessage {
+            source: source.to_string(),
+            base: base.to_string(),
+            quote: quote.to_string(),
+            price,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn get_base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn get_quote(&self) -> &str {
+        &self.quote
+    }
+
     pub fn get_key(&self) -> String {
         format!("{}-{}", self.source, self.get_symbol())
     }
@@ -36,52 +99,74 @@ impl fmt::Display for WsMessage {
     }
 }
 
-impl From<BinanceMessage> for WsMessage {
-    fn from(msg: BinanceMessage) -> Self {
-        let symbol = get_symbol(msg.s.as_str());
+impl WsMessage {
+    pub fn from_binance(info: &SymbolInfo, msg: BinanceMessage) -> Self {
+        let mut ws_message = WsMessage::new(&info.source, &info.base, &info.quote, msg.c);
+        ws_message.open = msg.o;
+        ws_message.high = msg.h;
+        ws_message.low = msg.l;
+        ws_message.volume = msg.v;
+        ws_message.quote_volume = msg.q;
+        ws_message.best_bid = msg.b;
+        ws_message.best_ask = msg.a;
+        ws_message.event_time = msg.e;
 
-        let (base, quote) = match symbol.split_once('-') {
-            Some((base, quote)) => (base.to_string(), quote.to_string()),
-            None => ("".to_string(), "".to_string()),
-        };
+        ws_message
+    }
 
-        WsMessage {
-            source: "binance".to_string(),
-            price: msg.c,
-            base,
-            quote,
-        }
+    pub fn from_coinbase(info: &SymbolInfo, msg: CoinbaseMessage) -> Self {
+        let mut ws_message = WsMessage::new(&info.source, &info.base, &info.quote, msg.price);
+        ws_message.open = msg.open_24h;
+        ws_message.high = msg.high_24h;
+        ws_message.low = msg.low_24h;
+        ws_message.volume = msg.volume_24h;
+        ws_message.best_bid = msg.best_bid;
+        ws_message.best_ask = msg.best_ask;
+        ws_message.event_time = msg
+            .time
+            .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+            .map(|time| time.timestamp_millis());
+
+        ws_message
     }
-}
 
-impl From<CoinbaseMessage> for WsMessage {
-    fn from(msg: CoinbaseMessage) -> Self {
-        let (base, quote) = match msg.product_id.split_once('-') {
-            Some((base, quote)) => (base.to_string(), quote.to_string()),
-            None => ("".to_string(), "".to_string()),
-        };
+    pub fn from_kraken(info: &SymbolInfo, msg: KrakenTicker) -> Self {
+        let mut ws_message = WsMessage::new(&info.source, &info.base, &info.quote, msg.last);
+        ws_message.high = msg.high.map(|high| high.to_string());
+        ws_message.low = msg.low.map(|low| low.to_string());
+        ws_message.volume = msg.volume.map(|volume| volume.to_string());
+        ws_message.best_bid = msg.bid.map(|bid| bid.to_string());
+        ws_message.best_ask = msg.ask.map(|ask| ask.to_string());
 
-        WsMessage {
-            source: "coinbase".to_string(),
-            price: msg.price,
-            base,
-            quote,
-        }
+        ws_message
     }
-}
 
-fn get_symbol(symbol: &str) -> String {
-    let known_quote_currencies = [
-        "USDT", "BTC", "ETH", "BNB", "DAI", "USD", "EUR", "USDC", "TRY", "BRL", "ZAR", "ARS",
-        "RON", "XRP", "UAH", "BIDR", "NGN", "PLN", "RUB", "DOGE", "IDRT", "TRX",
-    ];
-    for &quote in known_quote_currencies.iter() {
-        if symbol.ends_with(quote) {
-            if let Some(base) = symbol.strip_suffix(quote) {
-                return format!("{}-{}", base, quote);
-            }
-        }
+    pub fn from_okx(info: &SymbolInfo, msg: OkxTicker) -> Self {
+        let mut ws_message = WsMessage::new(&info.source, &info.base, &info.quote, msg.last);
+        ws_message.open = msg.open_24h;
+        ws_message.high = msg.high_24h;
+        ws_message.low = msg.low_24h;
+        ws_message.volume = msg.vol_24h;
+        ws_message.quote_volume = msg.vol_ccy_24h;
+        ws_message.best_bid = msg.bid_px;
+        ws_message.best_ask = msg.ask_px;
+        ws_message.event_time = msg.ts.and_then(|ts| ts.parse().ok());
+
+        ws_message
+    }
+
+    pub fn from_bybit(info: &SymbolInfo, msg: BybitMessage) -> Self {
+        let ticker = msg.data;
+
+        let mut ws_message =
+            WsMessage::new(&info.source, &info.base, &info.quote, ticker.last_price);
+        ws_message.open = ticker.prev_price_24h;
+        ws_message.high = ticker.high_price_24h;
+        ws_message.low = ticker.low_price_24h;
+        ws_message.volume = ticker.volume_24h;
+        ws_message.quote_volume = ticker.turnover_24h;
+        ws_message.event_time = msg.ts;
+
+        ws_message
     }
-    warn!("Unknown symbol: {}", symbol);
-    "".to_string()
 }
@@ -0,0 +1,288 @@
+//! Harness for running the connectors against the mock exchange. Redis is
+//! replaced by an in-process stand-in, so the tests need nothing but cargo.
+use rust_ticker_server::{
+    config::Config,
+    services::{
+        assets::AssetRegistry,
+        candles::CandleStore,
+        connector::{watch_symbols, ExchangeConnector},
+        dead_letters::DeadLetterStore,
+        latency::LatencyTracker,
+        order_book::OrderBookStore,
+        price_history::PriceHistory,
+        reconnect::ReconnectPolicy,
+        supervisor::{FeedStatus, Supervisor},
+        symbols::{SymbolFilter, SymbolRegistry},
+        ticker_store::TickerStore,
+    },
+    AppContext,
+};
+use serde_json::{json, Value};
+use sqlx::{sqlite::SqlitePoolOptions, Executor};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    process::{Child, Command},
+    sync::Arc,
+    thread,
+};
+use tokio::{
+    sync::broadcast,
+    time::{sleep, timeout, Duration, Instant},
+};
+use tungstenite::Message;
+
+/// Longest a test waits for the feeds to get somewhere
+pub const WAIT: Duration = Duration::from_secs(15);
+
+/// `mock_exchange` binary listening on a free port, killed on drop
+pub struct MockExchange {
+    child: Child,
+    port: u16,
+}
+
+impl MockExchange {
+    /// `disconnect_secs` and `malformed_every` are the `MOCK_*` fault settings
+    pub async fn start(disconnect_secs: u64, malformed_every: u64) -> Self {
+        let port = free_port();
+        let child = Command::new(env!("CARGO_BIN_EXE_mock_exchange"))
+            .env("MOCK_PORT", port.to_string())
+            .env("MOCK_TICK_MS", "100")
+            .env("MOCK_DISCONNECT_SECS", disconnect_secs.to_string())
+            .env("MOCK_MALFORMED_EVERY", malformed_every.to_string())
+            .spawn()
+            .expect("Failed to start mock_exchange");
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(started.elapsed() < WAIT, "mock_exchange did not start");
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        MockExchange { child, port }
+    }
+
+    /// Config pointing every venue at the mock and Redis at `redis_url`
+    pub fn config(&self, redis_url: &str) -> Config {
+        let http = format!("http://127.0.0.1:{}", self.port);
+        let ws = format!("ws://127.0.0.1:{}", self.port);
+
+        let config = json!({
+            "binance_ws_url": format!("{}/binance/stream", ws),
+            "binance_api_url": format!("{}/binance", http),
+            "coinbase_ws_url": format!("{}/coinbase", ws),
+            "coinbase_api_url": format!("{}/coinbase", http),
+            "kraken_ws_url": format!("{}/kraken/v2", ws),
+            "kraken_api_url": format!("{}/kraken", http),
+            "okx_ws_url": format!("{}/okx/ws/v5/public", ws),
+            "okx_api_url": format!("{}/okx", http),
+            "bybit_ws_url": format!("{}/bybit/v5/public/spot", ws),
+            "bybit_api_url": format!("{}/bybit", http),
+            "reconnect_initial_delay_ms": 100,
+            "reconnect_max_delay_ms": 500,
+            "fallback_after_secs": 0,
+            "database_url": "sqlite::memory:",
+            "redis_url": redis_url,
+        });
+
+        serde_json::from_value(config).expect("Invalid test config")
+    }
+}
+
+impl Drop for MockExchange {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .expect("No free port")
+}
+
+/// Answers every command with nil, so that each `SET NX GET` of a price
+/// looks like a new price and gets published
+pub fn fake_redis() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind fake Redis");
+    let url = format!("redis://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || serve_redis(stream));
+        }
+    });
+
+    url
+}
+
+fn serve_redis(stream: TcpStream) {
+    let mut writer = stream.try_clone().expect("Failed to clone Redis stream");
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+
+        // Commands are arrays of bulk strings, e.g. `*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n`
+        if let Some(count) = line.trim_end().strip_prefix('*') {
+            for _ in 0..count.parse::<usize>().unwrap_or(0) {
+                line.clear();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let len = line.trim_end()[1..].parse::<usize>().unwrap_or(0);
+
+                let mut argument = vec![0; len + 2];
+                if reader.read_exact(&mut argument).is_err() {
+                    return;
+                }
+            }
+        }
+
+        if writer.write_all(b"$-1\r\n").is_err() {
+            return;
+        }
+    }
+}
+
+/// Context with the migrations applied to an in-memory database, and a
+/// receiver of the tickers sent to the websocket clients
+pub async fn app_context(config: Config) -> (AppContext, broadcast::Receiver<Message>) {
+    // A single connection which is never closed keeps the in-memory database
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect(&config.database_url)
+        .await
+        .expect("Failed to open database");
+
+    let mut migrations = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/db/sql"))
+        .expect("No migrations")
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    migrations.sort();
+
+    // The seed data predates the fixed foreign keys, so the migrations run
+    // without them, as the sqlite shell applies them
+    pool.execute("PRAGMA foreign_keys = OFF").await.unwrap();
+    for migration in migrations {
+        let sql = fs::read_to_string(&migration).unwrap();
+        pool.execute(sql.as_str())
+            .await
+            .unwrap_or_else(|err| panic!("{} failed: {}", migration.display(), err));
+    }
+    pool.execute("PRAGMA foreign_keys = ON").await.unwrap();
+
+    let assets = AssetRegistry::load(&pool)
+        .await
+        .expect("Failed to load currencies");
+    let symbols = SymbolRegistry::new(assets.clone(), SymbolFilter::new(&config), None);
+
+    let (ticker_tx, ticker_rx) = broadcast::channel(1000);
+    let (book_tx, _) = broadcast::channel(100);
+    let (trade_tx, _) = broadcast::channel(100);
+    let (candle_tx, _) = broadcast::channel(100);
+    let (candles, _) = CandleStore::new();
+    let (prices, _) = PriceHistory::new();
+
+    let app_context = AppContext {
+        db_connection: pool,
+        dead_letters: DeadLetterStore::new(config.dead_letter_capacity),
+        config,
+        ticker_tx,
+        tickers: TickerStore::default(),
+        book_tx,
+        order_books: OrderBookStore::default(),
+        trade_tx,
+        candles,
+        candle_tx,
+        prices,
+        recorder: None,
+        symbols,
+        assets,
+        supervisor: Supervisor::default(),
+        latency: LatencyTracker::default(),
+    };
+
+    (app_context, ticker_rx)
+}
+
+/// Fetches the symbols of `connector` and runs it under the supervisor, the
+/// way the server does
+pub fn spawn(app_context: &AppContext, connector: Arc<dyn ExchangeConnector>) {
+    let retry_policy = ReconnectPolicy::new(&app_context.config);
+    let symbols = watch_symbols(connector.clone(), retry_policy, None);
+
+    app_context
+        .supervisor
+        .spawn(app_context.clone(), connector, symbols);
+}
+
+/// Next ticker of `source` sent to the websocket clients
+pub async fn next_ticker(ticker_rx: &mut broadcast::Receiver<Message>, source: &str) -> Value {
+    let receive = async {
+        loop {
+            let text = match ticker_rx.recv().await {
+                Ok(Message::Text(text)) => text,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => panic!("Ticker channel closed"),
+            };
+
+            let ticker: Value = serde_json::from_str(&text).expect("Ticker is not JSON");
+            if ticker["source"] == source {
+                return ticker;
+            }
+        }
+    };
+
+    timeout(WAIT, receive)
+        .await
+        .unwrap_or_else(|_| panic!("No {} ticker", source))
+}
+
+/// Polls `check` until it holds
+pub async fn wait_until(check: impl Fn() -> bool) {
+    let started = Instant::now();
+
+    while !check() {
+        assert!(started.elapsed() < WAIT, "Condition never held");
+        sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Status of the only feed named `name #n`, once `check` holds for it
+pub async fn wait_for_feed(
+    app_context: &AppContext,
+    name: &str,
+    check: impl Fn(&FeedStatus) -> bool,
+) -> FeedStatus {
+    let prefix = format!("{} #", name);
+    let started = Instant::now();
+
+    loop {
+        let status = app_context
+            .supervisor
+            .feeds()
+            .into_iter()
+            .find(|status| status.feed.starts_with(&prefix));
+
+        match status {
+            Some(status) if check(&status) => return status,
+            status => assert!(
+                started.elapsed() < WAIT,
+                "{} feed never got there: {:?}",
+                name,
+                status
+            ),
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+}
@@ -0,0 +1,226 @@
+//! Runs the venue connectors against the `mock_exchange` binary
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use rust_ticker_server::{
+    api::routes::health,
+    services::{
+        binance::{BinanceBookConnector, BinanceConnector},
+        bybit::BybitConnector,
+        coinbase::CoinbaseConnector,
+        connector::ExchangeConnector,
+        kraken::KrakenConnector,
+        okx::OkxConnector,
+        supervisor::FeedState,
+    },
+    AppContext,
+};
+use std::sync::Arc;
+
+mod common;
+
+use common::{
+    app_context, fake_redis, next_ticker, spawn, wait_for_feed, wait_until, MockExchange,
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn binance_connects_and_publishes_tickers() {
+    let mock = MockExchange::start(0, 0).await;
+    let (app_context, mut ticker_rx) = app_context(mock.config(&fake_redis())).await;
+
+    spawn(
+        &app_context,
+        Arc::new(BinanceConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    );
+
+    let ticker = next_ticker(&mut ticker_rx, "binance").await;
+    assert_eq!(ticker["quote"], "USDT");
+    assert!(ticker["price"].as_str().is_some());
+    assert!(ticker["event_time"].as_i64().is_some());
+
+    wait_for_feed(&app_context, "Binance", |status| {
+        status.state == FeedState::Live
+    })
+    .await;
+    wait_until(|| app_context.tickers.get("binance", "BTC", "USDT").is_some()).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn coinbase_connects_and_publishes_tickers() {
+    let mock = MockExchange::start(0, 0).await;
+    let (app_context, mut ticker_rx) = app_context(mock.config(&fake_redis())).await;
+
+    spawn(
+        &app_context,
+        Arc::new(CoinbaseConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    );
+
+    let ticker = next_ticker(&mut ticker_rx, "coinbase").await;
+    assert_eq!(ticker["quote"], "USDT");
+    assert!(ticker["best_bid"].as_str().is_some());
+
+    wait_for_feed(&app_context, "Coinbase", |status| {
+        status.state == FeedState::Live
+    })
+    .await;
+    wait_until(|| app_context.tickers.get("coinbase", "ETH", "USDT").is_some()).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn feeds_reconnect_after_the_venue_drops_them() {
+    let mock = MockExchange::start(1, 0).await;
+    let (app_context, mut ticker_rx) = app_context(mock.config(&fake_redis())).await;
+
+    spawn(
+        &app_context,
+        Arc::new(BinanceConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    );
+    spawn(
+        &app_context,
+        Arc::new(CoinbaseConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    );
+
+    for name in ["Binance", "Coinbase"] {
+        let first =
+            wait_for_feed(&app_context, name, |status| status.state == FeedState::Live).await;
+
+        // Live again after going down, without the task being restarted
+        let again = wait_for_feed(&app_context, name, |status| {
+            status.state == FeedState::Live && status.since > first.since
+        })
+        .await;
+        assert_eq!(again.restarts, 0);
+    }
+
+    next_ticker(&mut ticker_rx, "binance").await;
+    next_ticker(&mut ticker_rx, "coinbase").await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn malformed_frames_go_to_the_dead_letters() {
+    let mock = MockExchange::start(0, 3).await;
+    let (app_context, mut ticker_rx) = app_context(mock.config(&fake_redis())).await;
+
+    spawn(
+        &app_context,
+        Arc::new(BinanceConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    );
+    spawn(
+        &app_context,
+        Arc::new(CoinbaseConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    );
+
+    for name in ["Binance", "Coinbase"] {
+        wait_until(|| app_context.dead_letters.counts().contains_key(name)).await;
+
+        let letters = app_context.dead_letters.letters(Some(name));
+        assert!(!letters.is_empty());
+
+        // A bad frame does not take the connection down
+        let status = wait_for_feed(&app_context, name, |_| true).await;
+        assert_eq!(status.state, FeedState::Live);
+        assert_eq!(status.restarts, 0);
+    }
+
+    next_ticker(&mut ticker_rx, "binance").await;
+    next_ticker(&mut ticker_rx, "coinbase").await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn other_venues_publish_tickers() {
+    let mock = MockExchange::start(0, 0).await;
+    let (app_context, mut ticker_rx) = app_context(mock.config(&fake_redis())).await;
+
+    let connectors: Vec<Arc<dyn ExchangeConnector>> = vec![
+        Arc::new(KrakenConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+        Arc::new(OkxConnector::new(&app_context.config, &app_context.symbols)),
+        Arc::new(BybitConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    ];
+    for connector in connectors {
+        spawn(&app_context, connector);
+    }
+
+    for source in ["kraken", "okx", "bybit"] {
+        let ticker = next_ticker(&mut ticker_rx, source).await;
+        assert_eq!(ticker["quote"], "USDT");
+    }
+
+    // Kraken lists bitcoin as XBT over REST
+    wait_until(|| app_context.tickers.get("kraken", "BTC", "USDT").is_some()).await;
+}
+
+async fn health_status(app_context: &AppContext) -> StatusCode {
+    health(State(app_context.clone()))
+        .await
+        .into_response()
+        .status()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn health_only_counts_ticker_feeds() {
+    let mock = MockExchange::start(0, 0).await;
+    let (app_context, _ticker_rx) = app_context(mock.config(&fake_redis())).await;
+
+    assert_eq!(
+        health_status(&app_context).await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    spawn(
+        &app_context,
+        Arc::new(BinanceBookConnector::new(&app_context.config)),
+    );
+    wait_for_feed(&app_context, "Binance depth", |status| {
+        status.state == FeedState::Live
+    })
+    .await;
+    assert_eq!(
+        health_status(&app_context).await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    spawn(
+        &app_context,
+        Arc::new(BinanceConnector::new(
+            &app_context.config,
+            &app_context.symbols,
+        )),
+    );
+    wait_for_feed(&app_context, "Binance", |status| {
+        status.state == FeedState::Live
+    })
+    .await;
+    assert_eq!(health_status(&app_context).await, StatusCode::OK);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn replays_are_healthy_without_feeds() {
+    let mock = MockExchange::start(0, 0).await;
+    let mut config = mock.config(&fake_redis());
+    config.replay_file = "feeds.jsonl.gz".to_string();
+    let (app_context, _ticker_rx) = app_context(config).await;
+
+    assert_eq!(health_status(&app_context).await, StatusCode::OK);
+}